@@ -0,0 +1,418 @@
+use crate::config::{Config as Configuration, Section};
+use crate::connection::{self, TlsParams};
+use crate::connector::{self, Conflict, Connector, FieldType, Kind};
+use crate::prompt::Prompter;
+use crate::{check, config_load_error, output, report_check, secrets, validate, Error, Result};
+use clap::builder::{NonEmptyStringValueParser, PossibleValuesParser};
+use clap::{Arg, ArgMatches, Command, FromArgMatches, Subcommand};
+use colored::*;
+use std::path::Path;
+
+/// Connector and flags of `mycelial add source`, the subcommands are
+/// generated from the connector registry.
+#[derive(Debug)]
+pub struct SourceArgs(ConnectorArgs);
+
+/// Connector and flags of `mycelial add destination`.
+#[derive(Debug)]
+pub struct DestinationArgs(ConnectorArgs);
+
+#[derive(Debug)]
+struct ConnectorArgs {
+    connector: &'static Connector,
+    matches: ArgMatches,
+}
+
+// TLS flags, added to the query parameters of `--url`
+const TLS_FLAGS: [(&str, &str); 4] = [
+    (
+        "ssl-mode",
+        "TLS mode (`sslmode` for Postgres, `ssl-mode` for MySQL)",
+    ),
+    ("ssl-ca", "path to the CA certificate"),
+    ("ssl-cert", "path to the client certificate"),
+    ("ssl-key", "path to the client key"),
+];
+
+fn command(connector: &'static Connector) -> Command {
+    let mut command = Command::new(connector.name.as_str())
+        .about(connector.label.as_str())
+        .arg(
+            Arg::new("display_name")
+                .long("display-name")
+                .value_name("DISPLAY_NAME")
+                .help("name shown in the control plane")
+                .required(true)
+                .value_parser(NonEmptyStringValueParser::new()),
+        );
+    for field in connector.stored_fields() {
+        let mut arg = Arg::new(field.name.as_str())
+            .long(field.flag())
+            .value_name(field.flag().replace('-', "_").to_uppercase())
+            .help(field.help.as_str());
+        arg = match field.field_type {
+            // `--flag` alone sets it, `--flag false` unsets a field that
+            // defaults to true
+            FieldType::Bool => arg
+                .value_parser(clap::value_parser!(bool))
+                .num_args(0..=1)
+                .default_missing_value("true"),
+            FieldType::Integer => {
+                arg.value_parser(clap::value_parser!(i64).range(field.min.unwrap_or(i64::MIN)..))
+            }
+            FieldType::Choice => arg.value_parser(PossibleValuesParser::new(
+                field.choices.iter().map(String::as_str),
+            )),
+            _ if field.is_optional() => arg,
+            _ => arg.value_parser(NonEmptyStringValueParser::new()),
+        };
+        arg = match (&field.default, field.field_type) {
+            (Some(default), _) => arg.default_value(default.as_str()),
+            (None, FieldType::Bool) => arg.default_value("false"),
+            (None, _) => arg.required(!field.is_optional()),
+        };
+        command = command.arg(arg);
+        if field.field_type == FieldType::Url {
+            for (flag, help) in TLS_FLAGS {
+                let arg = Arg::new(flag)
+                    .long(flag)
+                    .value_name(flag.replace('-', "_").to_uppercase())
+                    .help(help);
+                command = command.arg(arg);
+            }
+        }
+    }
+    command
+}
+
+fn augment(kind: Kind, mut command: Command) -> Command {
+    for connector in connector::installed().of_kind(kind) {
+        command = command.subcommand(self::command(connector));
+    }
+    command
+}
+
+impl ConnectorArgs {
+    fn from_matches(kind: Kind, matches: &ArgMatches) -> std::result::Result<Self, clap::Error> {
+        let (name, matches) = matches.subcommand().ok_or_else(|| {
+            clap::Error::raw(
+                clap::error::ErrorKind::MissingSubcommand,
+                format!("a {} type is required", kind),
+            )
+        })?;
+        let connector = connector::registry().by_name(kind, name).ok_or_else(|| {
+            clap::Error::raw(
+                clap::error::ErrorKind::InvalidSubcommand,
+                format!("unknown {} type `{}`", kind, name),
+            )
+        })?;
+        Ok(ConnectorArgs {
+            connector,
+            matches: matches.clone(),
+        })
+    }
+
+    fn display_name(&self) -> &str {
+        self.matches.get_one::<String>("display_name").unwrap()
+    }
+}
+
+macro_rules! connector_subcommand {
+    ($args:ident, $kind:expr) => {
+        impl FromArgMatches for $args {
+            fn from_arg_matches(matches: &ArgMatches) -> std::result::Result<Self, clap::Error> {
+                ConnectorArgs::from_matches($kind, matches).map($args)
+            }
+
+            fn update_from_arg_matches(
+                &mut self,
+                matches: &ArgMatches,
+            ) -> std::result::Result<(), clap::Error> {
+                *self = Self::from_arg_matches(matches)?;
+                Ok(())
+            }
+        }
+
+        impl Subcommand for $args {
+            // only built when `add` is what is being parsed
+            fn augment_subcommands(command: Command) -> Command {
+                command.defer(|command| augment($kind, command))
+            }
+
+            fn augment_subcommands_for_update(command: Command) -> Command {
+                command.defer(|command| augment($kind, command))
+            }
+
+            fn has_subcommand(name: &str) -> bool {
+                connector::installed().by_name($kind, name).is_some()
+            }
+        }
+    };
+}
+
+connector_subcommand!(SourceArgs, Kind::Source);
+connector_subcommand!(DestinationArgs, Kind::Destination);
+
+fn load_existing(config_file_name: &str, kind: Kind, display_name: &str) -> Result<Configuration> {
+    if !Path::new(config_file_name).exists() {
+        return Err(Error::Config(format!(
+            "config file `{}` does not exist, run `mycelial init` first",
+            config_file_name
+        ))
+        .into());
+    }
+    let config = Configuration::load(config_file_name)
+        .map_err(|error| config_load_error(config_file_name, error))?;
+    if config.has_display_name(kind, display_name) {
+        return Err(Error::Config(format!(
+            "a {} named `{}` already exists in `{}`",
+            kind, display_name, config_file_name
+        ))
+        .into());
+    }
+    Ok(config)
+}
+
+// merges the TLS flags into the url, replacing TLS settings already in it
+fn with_tls(url: String, params: &TlsParams, matches: &ArgMatches) -> Result<String> {
+    let flag = |name: &str| matches.get_one::<String>(name).cloned();
+    let files = [
+        ("--ssl-ca", params.ca, flag("ssl-ca")),
+        ("--ssl-cert", params.cert, flag("ssl-cert")),
+        ("--ssl-key", params.key, flag("ssl-key")),
+    ];
+    let ssl_mode = flag("ssl-mode");
+    if ssl_mode.is_none() && files.iter().all(|(_, _, path)| path.is_none()) {
+        return Ok(url);
+    }
+    let mode = ssl_mode.unwrap_or_else(|| params.default_mode.to_string());
+    params
+        .check_mode(&mode)
+        .map_err(|error| Error::Usage(format!("--ssl-mode: {}", error)))?;
+    // the url isn't re-serialized, that would encode secret references
+    let (base, query) = url.split_once('?').unwrap_or((&url, ""));
+    let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+    for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+        if !params.keys().contains(&key.as_ref()) {
+            serializer.append_pair(&key, &value);
+        }
+    }
+    serializer.append_pair(params.mode, &mode);
+    for (flag, key, path) in files {
+        if let Some(path) = path {
+            connection::check_file(&path)
+                .map_err(|error| Error::Usage(format!("{}: {}", flag, error)))?;
+            serializer.append_pair(key, &path);
+        }
+    }
+    Ok(format!("{}?{}", base, serializer.finish()))
+}
+
+// checks a flag value and converts it into what is stored in the config
+fn field_value(
+    connector: &Connector,
+    name: &str,
+    value: String,
+    matches: &ArgMatches,
+) -> Result<String> {
+    let field = connector.field(name).unwrap();
+    let flag = format!("--{}", field.flag());
+    let with_flag = |error: String| Error::Usage(format!("{}: {}", flag, error));
+    if field.is_text() {
+        secrets::check_references(&value).map_err(with_flag)?;
+    }
+    match field.field_type {
+        FieldType::Url => {
+            let spec = field.url.as_ref().unwrap();
+            let schemes: Vec<&str> = spec.schemes.iter().map(String::as_str).collect();
+            validate::check_connection_url(&value, &schemes).map_err(with_flag)?;
+            match connection::tls_params(&spec.schemes[0]) {
+                Some(tls) => with_tls(value, tls, matches),
+                None => Ok(value),
+            }
+        }
+        FieldType::File => {
+            connection::check_file(&value).map_err(with_flag)?;
+            Ok(value)
+        }
+        FieldType::Brokers => {
+            connection::check_brokers(&value).map_err(with_flag)?;
+            Ok(value)
+        }
+        FieldType::Account => {
+            Ok(connection::snowflake_account_identifier(&value).map_err(with_flag)?)
+        }
+        _ => Ok(value),
+    }
+}
+
+fn section_from_args(args: &ConnectorArgs) -> Result<Section> {
+    let ConnectorArgs { connector, matches } = args;
+    let mut fields = toml::Table::new();
+    for field in connector.stored_fields() {
+        let value = match field.field_type {
+            FieldType::Bool => {
+                toml::Value::Boolean(matches.get_one::<bool>(&field.name) == Some(&true))
+            }
+            FieldType::Integer => match matches.get_one::<i64>(&field.name) {
+                Some(value) => toml::Value::Integer(*value),
+                None => continue,
+            },
+            _ => match matches.get_one::<String>(&field.name) {
+                // optional values equal to their default are left out
+                Some(value)
+                    if field.is_optional()
+                        && (value.is_empty() || field.default.as_ref() == Some(value)) =>
+                {
+                    continue
+                }
+                Some(value) => toml::Value::String(field_value(
+                    connector,
+                    &field.name,
+                    value.clone(),
+                    matches,
+                )?),
+                None => continue,
+            },
+        };
+        fields.insert(field.name.clone(), value);
+    }
+    let flag = |field: &connector::Field| format!("--{}", field.flag());
+    if let Some(conflict) = connector.conflicts(&fields).into_iter().next() {
+        let message = match conflict {
+            Conflict::Missing(missing, condition) => {
+                let flags: Vec<String> = missing.iter().map(|field| flag(field)).collect();
+                format!(
+                    "{} {} required {}",
+                    connector::join(&flags),
+                    if flags.len() == 1 { "is" } else { "are" },
+                    connector.describe(condition, &fields)
+                )
+            }
+            Conflict::NoneOf(options) => {
+                let flags: Vec<String> = options.iter().map(|field| flag(field)).collect();
+                format!("one of {} is required", flags.join(" or "))
+            }
+            Conflict::Both(first, second) => {
+                format!("{} and {} can't both be set", flag(first), flag(second))
+            }
+            Conflict::Requires(field, required) => {
+                format!("{} requires {}", flag(field), flag(required))
+            }
+        };
+        return Err(Error::Usage(message).into());
+    }
+    Ok(Section {
+        section_type: connector.section_type.clone(),
+        display_name: args.display_name().to_string(),
+        fields,
+    })
+}
+
+/// Builds a section from `--flag=value` arguments, with the same checks and
+/// defaults as `mycelial add`.
+pub(crate) fn section_from_flags(
+    connector: &'static Connector,
+    display_name: &str,
+    flags: Vec<String>,
+) -> Result<Section> {
+    let flags = [format!("--display-name={}", display_name)]
+        .into_iter()
+        .chain(flags);
+    let matches = command(connector)
+        .no_binary_name(true)
+        .try_get_matches_from(flags)
+        .map_err(|error| {
+            // first line of clap's message, without its `error: ` prefix
+            let message = error.to_string();
+            let message = message.lines().next().unwrap_or_default();
+            message.trim_start_matches("error: ").to_string()
+        })?;
+    section_from_args(&ConnectorArgs { connector, matches })
+}
+
+fn add_from_args(
+    p: &mut dyn Prompter,
+    config_file_name: &str,
+    kind: Kind,
+    args: ConnectorArgs,
+    check: bool,
+    save_anyway: bool,
+) -> Result<()> {
+    let display_name = args.display_name().to_string();
+    let mut config = load_existing(config_file_name, kind, &display_name)?;
+    let section = section_from_args(&args)?;
+    if check
+        && !report_check(
+            &display_name,
+            check::run(p, args.connector, &section.fields),
+        )
+        && !save_anyway
+    {
+        return Err(Error::Connection(format!(
+            "connection test of `{}` failed, pass --save-anyway to add it regardless",
+            display_name
+        ))
+        .into());
+    }
+    config.add_section(kind, section);
+    match config.save(config_file_name) {
+        Ok(_) => {
+            output::say(
+                format!("added {} `{}` to {}", kind, display_name, config_file_name).green(),
+            );
+            output::set(
+                "added",
+                serde_json::json!({
+                    "kind": kind.to_string(),
+                    "type": args.connector.section_type,
+                    "display_name": display_name,
+                    "config": config_file_name,
+                }),
+            );
+            Ok(())
+        }
+        Err(error) => Err(Error::Save(format!(
+            "error creating config file `{}`: {}",
+            config_file_name, error
+        ))
+        .into()),
+    }
+}
+
+/// Adds a source to an existing config file without prompting, testing its
+/// connection first with `check`.
+pub fn add_source_from_args(
+    p: &mut dyn Prompter,
+    config_file_name: &str,
+    args: SourceArgs,
+    check: bool,
+    save_anyway: bool,
+) -> Result<()> {
+    add_from_args(
+        p,
+        config_file_name,
+        Kind::Source,
+        args.0,
+        check,
+        save_anyway,
+    )
+}
+
+/// Adds a destination to an existing config file without prompting.
+pub fn add_destination_from_args(
+    p: &mut dyn Prompter,
+    config_file_name: &str,
+    args: DestinationArgs,
+    check: bool,
+    save_anyway: bool,
+) -> Result<()> {
+    add_from_args(
+        p,
+        config_file_name,
+        Kind::Destination,
+        args.0,
+        check,
+        save_anyway,
+    )
+}
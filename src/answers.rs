@@ -0,0 +1,284 @@
+//! Answers file for `mycelial init --answers`, so a config can be created
+//! without a terminal.
+//!
+//! The file holds what the prompts would ask for:
+//!
+//! ```toml
+//! [node]
+//! display_name = "My Daemon"
+//! id = "daemon"
+//! auth_token = "${env:MYCELIAL_TOKEN}"
+//!
+//! [server]
+//! endpoint = "http://localhost:7777"
+//!
+//! [[sources]]
+//! type = "sqlite"
+//! display_name = "SQLite Source"
+//! path = "data.db"
+//! query = "select * from test"
+//! ```
+//!
+//! Sources and destinations take the `mycelial add` subcommand as their
+//! `type`, or the type written to the config, and one answer per flag, named
+//! like the config field. `[[pipelines]]` are copied as they are.
+
+use crate::add;
+use crate::config::{Config as Configuration, Pipeline};
+use crate::connector::{self, Connector, FieldType, Kind};
+use crate::{secrets, validate, Error, Result};
+use serde::Deserialize;
+use uuid::Uuid;
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct Answers {
+    #[serde(default)]
+    node: NodeAnswers,
+    #[serde(default)]
+    server: ServerAnswers,
+    #[serde(default)]
+    sources: Vec<toml::Table>,
+    #[serde(default)]
+    destinations: Vec<toml::Table>,
+    #[serde(default)]
+    pipelines: Vec<Pipeline>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct NodeAnswers {
+    display_name: Option<String>,
+    /// a random suffix is appended, like the `Daemon ID` prompt does
+    id: Option<String>,
+    storage_path: Option<String>,
+    auth_token: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ServerAnswers {
+    endpoint: Option<String>,
+}
+
+// answers every connector with a url takes, besides its fields
+const TLS_ANSWERS: [&str; 4] = ["ssl_mode", "ssl_ca", "ssl_cert", "ssl_key"];
+
+// a source or destination of the answers file
+struct Entry<'a> {
+    location: String,
+    kind: Kind,
+    connector: &'static Connector,
+    table: &'a toml::Table,
+}
+
+impl Entry<'_> {
+    fn display_name(&self) -> &str {
+        self.table
+            .get("display_name")
+            .and_then(toml::Value::as_str)
+            .unwrap_or_default()
+    }
+
+    // answers the prompts would insist on
+    fn missing(&self) -> Vec<String> {
+        let required = self
+            .connector
+            .stored_fields()
+            .filter(|field| field.field_type != FieldType::Bool)
+            .filter(|field| field.default.is_none() && !field.is_optional())
+            .map(|field| field.name.as_str());
+        std::iter::once("display_name")
+            .chain(required)
+            .filter(|name| !self.table.contains_key(*name))
+            .map(|name| format!("{}.{}", self.location, name))
+            .collect()
+    }
+
+    fn unknown(&self) -> Vec<String> {
+        let has_url = self
+            .connector
+            .stored_fields()
+            .any(|field| field.field_type == FieldType::Url);
+        self.table
+            .keys()
+            .filter(|key| !["type", "display_name"].contains(&key.as_str()))
+            .filter(|key| self.connector.field(key).is_none())
+            .filter(|key| !(has_url && TLS_ANSWERS.contains(&key.as_str())))
+            .map(|key| format!("{}.{}", self.location, key))
+            .collect()
+    }
+
+    // the answers as `mycelial add` flags
+    fn flags(&self) -> std::result::Result<Vec<String>, String> {
+        let mut flags = Vec::new();
+        for (key, value) in self.table.iter() {
+            if key == "type" || key == "display_name" {
+                continue;
+            }
+            let flag = match self.connector.field(key) {
+                Some(field) => field.flag().to_string(),
+                None => key.replace('_', "-"),
+            };
+            let is_bool = self
+                .connector
+                .field(key)
+                .map(|field| field.field_type == FieldType::Bool)
+                .unwrap_or(false);
+            match value {
+                toml::Value::Boolean(value) if is_bool => {
+                    flags.push(format!("--{}={}", flag, value))
+                }
+                _ if is_bool => return Err(format!("`{}` must be true or false", key)),
+                toml::Value::String(value) => flags.push(format!("--{}={}", flag, value)),
+                toml::Value::Integer(value) => flags.push(format!("--{}={}", flag, value)),
+                _ => return Err(format!("`{}` must be a string or a number", key)),
+            }
+        }
+        Ok(flags)
+    }
+}
+
+fn entries_of<'a>(
+    kind: Kind,
+    tables: &'a [toml::Table],
+    missing: &mut Vec<String>,
+    unknown: &mut Vec<String>,
+) -> Vec<Entry<'a>> {
+    let section = match kind {
+        Kind::Source => "sources",
+        Kind::Destination => "destinations",
+    };
+    let mut entries = Vec::with_capacity(tables.len());
+    for (index, table) in tables.iter().enumerate() {
+        let location = format!("{}[{}]", section, index);
+        let connector_type = match table.get("type").and_then(toml::Value::as_str) {
+            Some(connector_type) => connector_type,
+            None => {
+                missing.push(format!("{}.type", location));
+                continue;
+            }
+        };
+        let registry = connector::registry();
+        match registry
+            .by_name(kind, connector_type)
+            .or_else(|| registry.find(kind, connector_type))
+        {
+            Some(connector) => entries.push(Entry {
+                location,
+                kind,
+                connector,
+                table,
+            }),
+            None => unknown.push(format!(
+                "{}.type (no {} type `{}`)",
+                location, kind, connector_type
+            )),
+        }
+    }
+    entries
+}
+
+/// Builds a config from the contents of an answers file. Every missing or
+/// unknown answer is reported at once, `endpoint` and `token` take the place
+/// of the answers they stand for.
+pub(crate) fn config(
+    answers_file_name: &str,
+    contents: &str,
+    endpoint: Option<String>,
+    token: Option<String>,
+) -> Result<Configuration> {
+    let answers: Answers = toml::from_str(contents).map_err(|error| {
+        Error::Config(format!(
+            "could not parse answers file `{}`: {}",
+            answers_file_name,
+            error.message()
+        ))
+    })?;
+    let mut missing = Vec::new();
+    let mut unknown = Vec::new();
+    let auth_token = token.or(answers.node.auth_token);
+    if auth_token.is_none() {
+        missing.push("node.auth_token".to_string());
+    }
+    let mut entries = entries_of(Kind::Source, &answers.sources, &mut missing, &mut unknown);
+    entries.extend(entries_of(
+        Kind::Destination,
+        &answers.destinations,
+        &mut missing,
+        &mut unknown,
+    ));
+    for entry in entries.iter() {
+        missing.extend(entry.missing());
+        unknown.extend(entry.unknown());
+    }
+    if !missing.is_empty() || !unknown.is_empty() {
+        let mut message = format!("answers file `{}` can't be used", answers_file_name);
+        if !missing.is_empty() {
+            message.push_str(&format!("\nmissing answers: {}", missing.join(", ")));
+        }
+        if !unknown.is_empty() {
+            message.push_str(&format!("\nunknown answers: {}", unknown.join(", ")));
+        }
+        return Err(Error::Config(message).into());
+    }
+
+    let auth_token = auth_token.unwrap();
+    secrets::check_references(&auth_token)
+        .map_err(|error| Error::Config(format!("auth_token: {}", error)))?;
+    let mut config = Configuration::new();
+    config.set_node(
+        answers
+            .node
+            .display_name
+            .unwrap_or_else(|| "My Daemon".to_string()),
+        format!(
+            "{}-{}",
+            answers.node.id.as_deref().unwrap_or("daemon"),
+            Uuid::new_v4()
+        ),
+        answers
+            .node
+            .storage_path
+            .unwrap_or_else(|| "daemon.db".to_string()),
+        auth_token,
+    );
+    config.set_server(
+        endpoint
+            .or(answers.server.endpoint)
+            .unwrap_or_else(|| "http://localhost:7777".to_string()),
+    );
+    for entry in entries {
+        let display_name = entry.display_name();
+        let with_location = |error: String| Error::Config(format!("{}: {}", entry.location, error));
+        if config.has_display_name(entry.kind, display_name) {
+            return Err(with_location(format!(
+                "a {} named `{}` already exists",
+                entry.kind, display_name
+            ))
+            .into());
+        }
+        let flags = entry.flags().map_err(with_location)?;
+        let section = add::section_from_flags(entry.connector, display_name, flags)
+            .map_err(|error| with_location(error.to_string()))?;
+        config.add_section(entry.kind, section);
+    }
+    for pipeline in answers.pipelines {
+        config.add_pipeline(pipeline);
+    }
+    // pipelines are the only answers left unchecked
+    let contents = toml::to_string(&config)?;
+    let problems: Vec<String> = validate::validate(&contents)
+        .into_iter()
+        .map(|diagnostic| diagnostic.message)
+        .collect();
+    if !problems.is_empty() {
+        return Err(Error::Config(format!(
+            "answers file `{}` has problems: {}",
+            answers_file_name,
+            problems.join("; ")
+        ))
+        .into());
+    }
+    Ok(config)
+}
@@ -0,0 +1,437 @@
+//! Connectivity checks of sources and destinations, run by `mycelial add
+//! --check` and `mycelial config test`.
+//!
+//! Which check a connector gets is part of its descriptor, see
+//! [`crate::connector::Check`]. Secret references are resolved before
+//! connecting, nothing is written to the config.
+
+use crate::connection::{self, TlsParams, MYSQL_TLS, POSTGRES_TLS};
+use crate::connector::{CheckType, Connector, Kind};
+use crate::prompt::Prompter;
+use crate::secrets;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{
+    CertificateError, ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use std::fs::{self, OpenOptions};
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const BROKER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Result of a check.
+pub(crate) enum Outcome {
+    Passed(String),
+    Failed(String),
+    /// the connector has no check
+    Skipped(String),
+}
+
+/// Checks that the daemon will be able to reach the connector described by
+/// `table`.
+pub(crate) fn run(p: &mut dyn Prompter, connector: &Connector, table: &toml::Table) -> Outcome {
+    let check = match &connector.check {
+        Some(check) => check,
+        None => {
+            return Outcome::Skipped(format!(
+                "{} {}s can't be checked",
+                connector.section_type, connector.kind
+            ))
+        }
+    };
+    let value = connector.value_of(&check.field, table);
+    let url = matches!(check.check_type, CheckType::Postgres | CheckType::Mysql);
+    let value = match secrets::resolve_value(p, &value, url) {
+        Ok(value) => value,
+        Err(error) => return Outcome::Failed(error.to_string()),
+    };
+    let kind = connector.kind;
+    let result = match check.check_type {
+        CheckType::Sqlite => sqlite(kind, &value),
+        CheckType::File => file(kind, &value),
+        CheckType::Postgres => block_on(postgres(&value)),
+        CheckType::Mysql => block_on(mysql(&value)),
+        CheckType::Kafka => block_on(kafka(&value)),
+    };
+    match result {
+        Ok(detail) => Outcome::Passed(detail),
+        Err(error) => Outcome::Failed(error),
+    }
+}
+
+/// Runs a network check to completion from synchronous code, giving up
+/// after a while.
+pub(crate) fn block_on<T, F: Future<Output = Result<T, String>>>(check: F) -> Result<T, String> {
+    let check = async {
+        tokio::time::timeout(TIMEOUT, check)
+            .await
+            .unwrap_or_else(|_| Err(format!("timed out after {}s", TIMEOUT.as_secs())))
+    };
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => tokio::task::block_in_place(|| handle.block_on(check)),
+        Err(_) => tokio::runtime::Runtime::new()
+            .map_err(|error| error.to_string())?
+            .block_on(check),
+    }
+}
+
+fn sqlite(kind: Kind, path: &str) -> Result<String, String> {
+    use rusqlite::{Connection, OpenFlags};
+
+    let exists = Path::new(path).is_file();
+    let flags = match kind {
+        Kind::Source if !exists => return Err(format!("`{}` does not exist", path)),
+        Kind::Source => OpenFlags::SQLITE_OPEN_READ_ONLY,
+        // the daemon creates the database
+        Kind::Destination if !exists => {
+            writable_dir(path)?;
+            return Ok(format!("`{}` will be created", path));
+        }
+        Kind::Destination => OpenFlags::SQLITE_OPEN_READ_WRITE,
+    };
+    let connection = Connection::open_with_flags(path, flags)
+        .map_err(|error| format!("can't open `{}`: {}", path, error))?;
+    let tables: i64 = connection
+        .query_row(
+            "select count(*) from sqlite_master where type = 'table'",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|error| format!("can't read `{}`: {}", path, error))?;
+    if kind == Kind::Destination {
+        connection
+            .execute_batch("begin immediate; rollback;")
+            .map_err(|error| format!("can't write to `{}`: {}", path, error))?;
+    }
+    Ok(format!("opened `{}`, {} table(s)", path, tables))
+}
+
+fn file(kind: Kind, path: &str) -> Result<String, String> {
+    let exists = Path::new(path).is_file();
+    match kind {
+        Kind::Source => fs::File::open(path)
+            .map(|_| format!("`{}` is readable", path))
+            .map_err(|error| format!("can't read `{}`: {}", path, error)),
+        Kind::Destination if exists => OpenOptions::new()
+            .append(true)
+            .open(path)
+            .map(|_| format!("`{}` is writable", path))
+            .map_err(|error| format!("can't write to `{}`: {}", path, error)),
+        Kind::Destination => {
+            writable_dir(path)?;
+            Ok(format!("`{}` will be created", path))
+        }
+    }
+}
+
+// checks that a file can be created next to `path`
+fn writable_dir(path: &str) -> Result<(), String> {
+    let dir = match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if !dir.is_dir() {
+        return Err(format!("directory `{}` does not exist", dir.display()));
+    }
+    let probe = dir.join(format!(".mycelial-check-{}", uuid::Uuid::new_v4()));
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .map_err(|error| format!("can't write to `{}`: {}", dir.display(), error))?;
+    let _ = fs::remove_file(probe);
+    Ok(())
+}
+
+// TLS settings taken out of a connection url
+struct Tls {
+    mode: Option<String>,
+    ca: Option<String>,
+    cert: Option<String>,
+    key: Option<String>,
+}
+
+// splits the TLS query parameters, which the drivers name differently,
+// from the rest of the url
+fn split_tls(url: &str, params: &TlsParams) -> Result<(String, Tls), String> {
+    let mut parsed = url::Url::parse(url).map_err(|error| error.to_string())?;
+    let mut tls = Tls {
+        mode: None,
+        ca: None,
+        cert: None,
+        key: None,
+    };
+    let mut rest = Vec::new();
+    for (key, value) in parsed.query_pairs() {
+        let value = value.to_string();
+        match key.as_ref() {
+            key if key == params.mode => tls.mode = Some(value),
+            key if key == params.ca => tls.ca = Some(value),
+            key if key == params.cert => tls.cert = Some(value),
+            key if key == params.key => tls.key = Some(value),
+            key => rest.push((key.to_string(), value)),
+        }
+    }
+    match rest.is_empty() {
+        true => parsed.set_query(None),
+        false => {
+            parsed.query_pairs_mut().clear().extend_pairs(rest);
+        }
+    }
+    Ok((parsed.to_string(), tls))
+}
+
+// how much of the server certificate is verified
+#[derive(Clone, Copy, PartialEq)]
+enum Verify {
+    Nothing,
+    Chain,
+    Full,
+}
+
+#[derive(Debug)]
+struct Verifier {
+    // `None` accepts any certificate
+    webpki: Option<Arc<WebPkiServerVerifier>>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for Verifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let webpki = match &self.webpki {
+            Some(webpki) => webpki,
+            None => return Ok(ServerCertVerified::assertion()),
+        };
+        match webpki.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+        {
+            // `verify-ca` doesn't check the host name
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. },
+            )) => Ok(ServerCertVerified::assertion()),
+            result => result,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// The error and its causes, which the drivers often leave out of the
+/// message.
+pub(crate) fn describe(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        let cause_message = cause.to_string();
+        if !message.contains(&cause_message) {
+            message = format!("{}: {}", message, cause_message);
+        }
+        source = cause.source();
+    }
+    message
+}
+
+fn read_pem(path: &str) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|error| format!("can't read `{}`: {}", path, error))
+}
+
+fn tls_config(tls: &Tls, verify: Verify) -> Result<ClientConfig, String> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut roots = RootCertStore::empty();
+    match &tls.ca {
+        Some(ca) => {
+            for cert in rustls_pemfile::certs(&mut read_pem(ca)?.as_slice()) {
+                let cert =
+                    cert.map_err(|error| format!("invalid certificate `{}`: {}", ca, error))?;
+                roots
+                    .add(cert)
+                    .map_err(|error| format!("invalid certificate `{}`: {}", ca, error))?;
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|error| error.to_string())?;
+    let builder = match verify {
+        Verify::Full => builder.with_root_certificates(roots),
+        Verify::Chain | Verify::Nothing => {
+            let webpki = match verify {
+                Verify::Chain => Some(
+                    WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                        .build()
+                        .map_err(|error| error.to_string())?,
+                ),
+                _ => None,
+            };
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(Verifier { webpki, provider }))
+        }
+    };
+    match (&tls.cert, &tls.key) {
+        (Some(cert), Some(key)) => {
+            let certs = rustls_pemfile::certs(&mut read_pem(cert)?.as_slice())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|error| format!("invalid certificate `{}`: {}", cert, error))?;
+            let key = rustls_pemfile::private_key(&mut read_pem(key)?.as_slice())
+                .map_err(|error| format!("invalid key `{}`: {}", key, error))?
+                .ok_or_else(|| format!("no private key in `{}`", key))?;
+            builder
+                .with_client_auth_cert(certs, key)
+                .map_err(|error| error.to_string())
+        }
+        _ => Ok(builder.with_no_client_auth()),
+    }
+}
+
+/// Connects to Postgres with the TLS settings of the url.
+pub(crate) async fn postgres_client(url: &str) -> Result<tokio_postgres::Client, String> {
+    use tokio_postgres::config::SslMode;
+
+    let (base, tls) = split_tls(url, &POSTGRES_TLS)?;
+    let mut config: tokio_postgres::Config = base
+        .parse()
+        .map_err(|error: tokio_postgres::Error| error.to_string())?;
+    let mode = tls.mode.as_deref().unwrap_or("prefer");
+    let client = match mode {
+        "disable" => {
+            let (client, connection) = config
+                .ssl_mode(SslMode::Disable)
+                .connect(tokio_postgres::NoTls)
+                .await
+                .map_err(|error| describe(&error))?;
+            tokio::spawn(connection);
+            client
+        }
+        _ => {
+            let (ssl_mode, verify) = match mode {
+                "allow" | "prefer" => (SslMode::Prefer, Verify::Nothing),
+                "verify-ca" => (SslMode::Require, Verify::Chain),
+                "verify-full" => (SslMode::Require, Verify::Full),
+                _ => (SslMode::Require, Verify::Nothing),
+            };
+            let connector =
+                tokio_postgres_rustls::MakeRustlsConnect::new(tls_config(&tls, verify)?);
+            let (client, connection) = config
+                .ssl_mode(ssl_mode)
+                .connect(connector)
+                .await
+                .map_err(|error| describe(&error))?;
+            tokio::spawn(connection);
+            client
+        }
+    };
+    Ok(client)
+}
+
+async fn postgres(url: &str) -> Result<String, String> {
+    postgres_client(url)
+        .await?
+        .simple_query("select 1")
+        .await
+        .map_err(|error| describe(&error))?;
+    Ok(format!("logged in to {}", connection::url_target(url)))
+}
+
+/// Connects to MySQL with the TLS settings of the url.
+pub(crate) async fn mysql_conn(url: &str) -> Result<mysql_async::Conn, String> {
+    use mysql_async::{ClientIdentity, Conn, Opts, OptsBuilder, SslOpts};
+
+    let (base, tls) = split_tls(url, &MYSQL_TLS)?;
+    let opts = Opts::from_url(&base).map_err(|error| error.to_string())?;
+    let mode = tls.mode.as_deref().unwrap_or("PREFERRED");
+    let ssl_opts = match mode {
+        "DISABLED" => None,
+        _ => {
+            let mut ssl_opts = SslOpts::default()
+                .with_danger_accept_invalid_certs(matches!(mode, "PREFERRED" | "REQUIRED"))
+                .with_danger_skip_domain_validation(mode != "VERIFY_IDENTITY");
+            if let Some(ca) = &tls.ca {
+                ssl_opts = ssl_opts.with_root_certs(vec![Path::new(ca).to_path_buf().into()]);
+            }
+            if let (Some(cert), Some(key)) = (&tls.cert, &tls.key) {
+                ssl_opts = ssl_opts.with_client_identity(Some(ClientIdentity::new(
+                    Path::new(cert).to_path_buf().into(),
+                    Path::new(key).to_path_buf().into(),
+                )));
+            }
+            Some(ssl_opts)
+        }
+    };
+    let connect = |ssl_opts: Option<SslOpts>| {
+        Conn::new(OptsBuilder::from_opts(opts.clone()).ssl_opts(ssl_opts))
+    };
+    let result = match connect(ssl_opts).await {
+        // like the daemon, fall back to plain connections when TLS is only
+        // preferred
+        Err(_) if mode == "PREFERRED" => connect(None).await,
+        result => result,
+    };
+    result.map_err(|error| describe(&error))
+}
+
+async fn mysql(url: &str) -> Result<String, String> {
+    let mut conn = mysql_conn(url).await?;
+    mysql_async::prelude::Queryable::ping(&mut conn)
+        .await
+        .map_err(|error| describe(&error))?;
+    let _ = conn.disconnect().await;
+    Ok(format!("logged in to {}", connection::url_target(url)))
+}
+
+async fn kafka(brokers: &str) -> Result<String, String> {
+    let mut errors = Vec::new();
+    for broker in brokers.split(',').map(str::trim) {
+        match tokio::time::timeout(BROKER_TIMEOUT, tokio::net::TcpStream::connect(broker)).await {
+            Ok(Ok(_)) => return Ok(format!("reached broker {}", broker)),
+            Ok(Err(error)) => errors.push(format!("{}: {}", broker, error)),
+            Err(_) => errors.push(format!("{}: timed out", broker)),
+        }
+    }
+    Err(format!("no broker reachable ({})", errors.join(", ")))
+}
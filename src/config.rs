@@ -1,3 +1,6 @@
+use crate::connector::{self, Connector, FieldType, Kind};
+use crate::secrets;
+use percent_encoding::percent_decode_str;
 use serde::{Deserialize, Serialize};
 use std::fs;
 
@@ -8,6 +11,7 @@ impl Config {
             server: None,
             sources: None,
             destinations: None,
+            pipelines: None,
         }
     }
     pub fn set_node(
@@ -27,175 +31,236 @@ impl Config {
     pub fn set_server(&mut self, endpoint: String) {
         self.server = Some(Server { endpoint });
     }
-    pub fn add_sqlite_connector_source(
-        &mut self,
-        display_name: String,
-        origin: String,
-        path: String,
-        query: String,
-    ) {
-        self.add_source(Source::sqlite_connector {
-            display_name,
-            origin,
-            path,
-            query,
-        });
+    pub fn save<T: AsRef<str>>(&self, path: T) -> Result<(), Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let toml = toml::to_string(&self)?;
+        fs::write(path, toml)?;
+        Ok(())
     }
 
-    pub fn add_sqlite_connector_destination(
-        &mut self,
-        display_name: String,
-        path: String,
-        truncate: bool,
-    ) {
-        self.add_destination(Destination::sqlite_connector {
-            display_name,
-            path,
-            truncate,
-        });
+    pub fn load(path: &str) -> Result<Config, Box<dyn std::error::Error>> {
+        let contents = fs::read_to_string(path)?;
+        let config: Config = toml::from_str(&contents)?;
+        for (kind, sections) in config.sections() {
+            for section in sections {
+                if connector::registry()
+                    .find(kind, &section.section_type)
+                    .is_none()
+                {
+                    return Err(format!("unknown {} type `{}`", kind, section.section_type).into());
+                }
+            }
+        }
+        Ok(config)
     }
 
-    pub fn add_postgres_connector_destination(
-        &mut self,
-        display_name: String,
-        url: String,
-        schema: String,
-        truncate: bool,
-    ) {
-        self.add_destination(Destination::postgres_connector {
-            display_name,
-            url,
-            schema,
-            truncate,
-        });
+    // sources, then destinations
+    fn sections(&self) -> [(Kind, &[Section]); 2] {
+        [
+            (Kind::Source, self.sources.as_deref().unwrap_or_default()),
+            (
+                Kind::Destination,
+                self.destinations.as_deref().unwrap_or_default(),
+            ),
+        ]
     }
 
-    pub fn add_mysql_connector_destination(
-        &mut self,
-        display_name: String,
-        url: String,
-        truncate: bool,
-    ) {
-        self.add_destination(Destination::mysql_connector {
-            display_name,
-            url,
-            truncate,
-        });
+    /// Display names only have to be unique among the sources, or among the
+    /// destinations.
+    pub fn has_display_name(&self, kind: Kind, display_name: &str) -> bool {
+        self.sections()
+            .iter()
+            .filter(|(section_kind, _)| *section_kind == kind)
+            .flat_map(|(_, sections)| sections.iter())
+            .any(|section| section.display_name == display_name)
     }
-    pub fn add_kafka_destination(&mut self, display_name: String, brokers: String, topic: String) {
-        self.add_destination(Destination::kafka {
-            display_name,
-            brokers,
-            topic,
-        });
+
+    pub fn source_names(&self) -> Vec<String> {
+        self.sources
+            .iter()
+            .flatten()
+            .map(|source| source.display_name.clone())
+            .collect()
     }
 
-    pub fn add_excel_connector_source(
-        &mut self,
-        display_name: String,
-        path: String,
-        sheets: String,
-        strict: bool,
-    ) {
-        self.add_source(Source::excel_connector {
-            display_name,
-            path,
-            sheets,
-            strict,
-        });
+    pub fn destination_names(&self) -> Vec<String> {
+        self.destinations
+            .iter()
+            .flatten()
+            .map(|destination| destination.display_name.clone())
+            .collect()
     }
-    pub fn add_file_source(&mut self, display_name: String, path: String) {
-        self.add_source(Source::file { display_name, path });
+
+    pub(crate) fn names_of(&self, kind: Kind) -> Vec<String> {
+        match kind {
+            Kind::Source => self.source_names(),
+            Kind::Destination => self.destination_names(),
+        }
     }
-    pub fn add_file_destination(&mut self, display_name: String, path: String) {
-        self.add_destination(Destination::file { display_name, path });
+
+    pub fn pipelines(&self) -> &[Pipeline] {
+        self.pipelines.as_deref().unwrap_or_default()
     }
-    pub fn add_snowflake_connector_destination(
-        &mut self,
-        display_name: String,
-        username: String,
-        password: String,
-        role: String,
-        account_identifier: String,
-        warehouse: String,
-        database: String,
-        schema: String,
-        truncate: bool,
-    ) {
-        self.add_destination(Destination::snowflake {
-            display_name,
-            username,
-            password,
-            role,
-            account_identifier,
-            warehouse,
-            database,
-            schema,
-            truncate,
-        });
+
+    pub fn add_pipeline(&mut self, pipeline: Pipeline) {
+        self.pipelines.get_or_insert_with(Vec::new).push(pipeline);
     }
-    pub fn save<T: AsRef<str>>(&self, path: T) -> Result<(), Box<dyn std::error::Error>> {
-        let path = path.as_ref();
-        let toml = toml::to_string(&self)?;
-        fs::write(path, toml)?;
-        Ok(())
+
+    /// Lists sources followed by destinations; `index` is unique across both.
+    pub fn entries(&self) -> Vec<Entry> {
+        self.sections()
+            .into_iter()
+            .flat_map(|(kind, sections)| sections.iter().map(move |section| (kind, section)))
+            .enumerate()
+            .map(|(index, (kind, section))| Entry {
+                index,
+                kind,
+                section_type: section.section_type.clone(),
+                display_name: section.display_name.clone(),
+                target: section
+                    .connector(kind)
+                    .map(|connector| connector.target(&section.fields))
+                    .unwrap_or_default(),
+            })
+            .collect()
     }
 
-    pub fn load(path: &str) -> Result<Config, Box<dyn std::error::Error>> {
-        let contents = fs::read_to_string(path)?;
-        let config: Config = toml::from_str(&contents)?;
-        Ok(config)
+    /// Finds an entry by display name, or by its index in `entries()`. A
+    /// source is found before a destination with the same name, which can
+    /// still be found by its index.
+    pub fn find_entry(&self, name_or_index: &str) -> Option<Entry> {
+        let entries = self.entries();
+        let by_name = entries
+            .iter()
+            .position(|entry| entry.display_name == name_or_index);
+        let position = match by_name {
+            Some(position) => Some(position),
+            None => name_or_index
+                .parse::<usize>()
+                .ok()
+                .filter(|index| *index < entries.len()),
+        };
+        position.map(|position| entries.into_iter().nth(position).unwrap())
+    }
+
+    /// Returns an entry's fields, including its `type`.
+    pub fn entry_table(&self, index: usize) -> Option<toml::Table> {
+        let sources_len = self.sources.as_ref().map(Vec::len).unwrap_or(0);
+        let value = if index < sources_len {
+            toml::Table::try_from(&self.sources.as_ref()?[index])
+        } else {
+            toml::Table::try_from(self.destinations.as_ref()?.get(index - sources_len)?)
+        };
+        value.ok()
+    }
+
+    /// Renders an entry as TOML with passwords masked.
+    pub fn entry_to_string(&self, index: usize) -> Result<String, Box<dyn std::error::Error>> {
+        let mut table = self.entry_table(index).ok_or("no such entry")?;
+        let entry = self
+            .entries()
+            .into_iter()
+            .nth(index)
+            .ok_or("no such entry")?;
+        if let Some(connector) = connector::registry().find(entry.kind, &entry.section_type) {
+            redact_secrets(connector, &mut table);
+        }
+        Ok(toml::to_string(&table)?)
+    }
+
+    /// Replaces the entry at `index` with `section`, which must be of the
+    /// same kind.
+    pub(crate) fn replace_entry(&mut self, index: usize, section: Section) {
+        let sources_len = self.sources.as_ref().map(Vec::len).unwrap_or(0);
+        if index < sources_len {
+            if let Some(sources) = self.sources.as_mut() {
+                sources[index] = section;
+            }
+        } else if let Some(destinations) = self.destinations.as_mut() {
+            destinations[index - sources_len] = section;
+        }
+    }
+
+    /// Makes the pipelines reading from, or writing to, the `kind` named
+    /// `from` use `to` instead. Returns the names of the pipelines changed.
+    pub(crate) fn rename_in_pipelines(&mut self, kind: Kind, from: &str, to: &str) -> Vec<String> {
+        let mut changed = Vec::new();
+        for pipeline in self.pipelines.iter_mut().flatten() {
+            let names: Vec<&mut String> = match kind {
+                Kind::Source => vec![&mut pipeline.source],
+                Kind::Destination => pipeline.destinations.iter_mut().collect(),
+            };
+            let mut renamed = false;
+            for name in names.into_iter().filter(|name| name.as_str() == from) {
+                *name = to.to_string();
+                renamed = true;
+            }
+            if renamed {
+                changed.push(pipeline.name.clone());
+            }
+        }
+        changed
+    }
+
+    pub fn remove_entry(&mut self, index: usize) -> Option<Entry> {
+        let entry = self.entries().into_iter().nth(index)?;
+        let sources_len = self.sources.as_ref().map(Vec::len).unwrap_or(0);
+        if index < sources_len {
+            self.sources.as_mut()?.remove(index);
+        } else {
+            self.destinations.as_mut()?.remove(index - sources_len);
+        }
+        Some(entry)
     }
 
     pub fn get_node_storage_path(&self) -> Option<String> {
         self.node.as_ref().map(|node| node.storage_path.clone())
     }
 
-    pub(crate) fn add_postgres_connector_source(
-        &mut self,
-        display_name: String,
-        url: String,
-        origin: String,
-        query: String,
-        poll_interval: i32,
-    ) {
-        self.add_source(Source::postgres_connector {
-            display_name,
-            url,
-            origin,
-            query,
-            poll_interval,
-        });
+    pub(crate) fn add_section(&mut self, kind: Kind, section: Section) {
+        let sections = match kind {
+            Kind::Source => &mut self.sources,
+            Kind::Destination => &mut self.destinations,
+        };
+        sections.get_or_insert_with(Vec::new).push(section);
     }
+}
 
-    pub(crate) fn add_mysql_connector_source(
-        &mut self,
-        display_name: String,
-        url: String,
-        origin: String,
-        query: String,
-        poll_interval: i32,
-    ) {
-        self.add_source(Source::mysql_connector {
-            display_name,
-            url,
-            origin,
-            query,
-            poll_interval,
-        })
-    }
+/// A source or destination, as shown by `mycelial config list`.
+pub struct Entry {
+    pub index: usize,
+    pub(crate) kind: Kind,
+    pub section_type: String,
+    pub display_name: String,
+    pub target: String,
+}
 
-    fn add_destination(&mut self, destination: Destination) {
-        self.destinations = Some(self.destinations.take().unwrap_or_default());
-        if let Some(v) = self.destinations.as_mut() {
-            v.push(destination)
+const REDACTED: &str = "********";
+
+// secret references are left as they are, they don't reveal anything
+fn redact_secrets(connector: &Connector, table: &mut toml::Table) {
+    for field in connector.stored_fields() {
+        let value = match table.get_mut(&field.name) {
+            Some(toml::Value::String(value)) => value,
+            _ => continue,
+        };
+        if field.secret && !secrets::is_reference(value) {
+            *value = REDACTED.to_string();
+        } else if field.field_type == FieldType::Url {
+            redact_url_password(value);
         }
     }
+}
 
-    fn add_source(&mut self, source: Source) {
-        self.sources = Some(self.sources.take().unwrap_or_default());
-        if let Some(v) = self.sources.as_mut() {
-            v.push(source)
+fn redact_url_password(url: &mut String) {
+    if let Ok(mut parsed) = url::Url::parse(url) {
+        let is_secret = parsed
+            .password()
+            .map(|password| percent_decode_str(password).decode_utf8_lossy())
+            .map(|password| !secrets::is_reference(&password))
+            .unwrap_or(false);
+        if is_secret && parsed.set_password(Some(REDACTED)).is_ok() {
+            *url = parsed.to_string();
         }
     }
 }
@@ -204,8 +269,26 @@ impl Config {
 pub struct Config {
     node: Option<Node>,
     server: Option<Server>,
-    sources: Option<Vec<Source>>,
-    destinations: Option<Vec<Destination>>,
+    sources: Option<Vec<Section>>,
+    destinations: Option<Vec<Section>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pipelines: Option<Vec<Pipeline>>,
+}
+
+/// Connects a source to the destinations it feeds, by display name.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Pipeline {
+    pub name: String,
+    pub source: String,
+    pub destinations: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+}
+
+impl Pipeline {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -221,97 +304,19 @@ struct Server {
     endpoint: String,
 }
 
-#[derive(Serialize, Deserialize)]
-#[serde(tag = "type")]
-#[allow(non_camel_case_types)]
-enum Source {
-    excel_connector {
-        display_name: String,
-        path: String,
-        sheets: String,
-        strict: bool,
-    },
-    sqlite_physical_replication {
-        display_name: String,
-        journal_path: String,
-    },
-    hello_world {
-        interval_milis: u64,
-        message: String,
-        display_name: String,
-    },
-    sqlite_connector {
-        display_name: String,
-        origin: String,
-        path: String,
-        query: String,
-    },
-    postgres_connector {
-        display_name: String,
-        url: String,
-        origin: String,
-        query: String,
-        poll_interval: i32,
-    },
-    mysql_connector {
-        display_name: String,
-        url: String,
-        origin: String,
-        query: String,
-        poll_interval: i32,
-    },
-    file {
-        display_name: String,
-        path: String,
-    },
+/// A source or destination, its fields are described by the connector
+/// registered for its `type`.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct Section {
+    #[serde(rename = "type")]
+    pub section_type: String,
+    pub display_name: String,
+    #[serde(flatten)]
+    pub fields: toml::Table,
 }
 
-#[derive(Serialize, Deserialize)]
-#[serde(tag = "type")]
-#[allow(non_camel_case_types)]
-enum Destination {
-    sqlite_physical_replication {
-        display_name: String,
-        journal_path: String,
-        database_path: String,
-    },
-    hello_world {
-        display_name: String,
-    },
-    sqlite_connector {
-        display_name: String,
-        path: String,
-        truncate: bool,
-    },
-    postgres_connector {
-        display_name: String,
-        url: String,
-        schema: String,
-        truncate: bool,
-    },
-    mysql_connector {
-        display_name: String,
-        url: String,
-        truncate: bool,
-    },
-    kafka {
-        display_name: String,
-        brokers: String,
-        topic: String,
-    },
-    snowflake {
-        display_name: String,
-        username: String,
-        password: String,
-        role: String,
-        account_identifier: String,
-        warehouse: String,
-        database: String,
-        schema: String,
-        truncate: bool,
-    },
-    file {
-        display_name: String,
-        path: String,
-    },
+impl Section {
+    fn connector(&self, kind: Kind) -> Option<&'static Connector> {
+        connector::registry().find(kind, &self.section_type)
+    }
 }
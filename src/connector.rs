@@ -0,0 +1,602 @@
+//! Registry of the source and destination connectors.
+//!
+//! Each connector is described once by the fields it stores in the config.
+//! The interactive prompts, the `mycelial add` flags, `mycelial config
+//! validate` and the config entries themselves are generated from these
+//! descriptors. `mycelial add` and `mycelial config validate` ask the
+//! installed daemon for them with `myceliald --describe-connectors`, which
+//! prints them as JSON; everything else, and those two when that fails, use
+//! the descriptors bundled from `connectors.toml`.
+
+use crate::connection;
+use colored::*;
+use serde::Deserialize;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+const BUILTIN: &str = include_str!("connectors.toml");
+const DAEMON_ENV: &str = "MYCELIAL_DAEMON";
+// where `mycelial service add` installs the daemon
+const INSTALLED_DAEMON: &str = "/usr/local/bin/myceliald";
+const DESCRIBE_FLAG: &str = "--describe-connectors";
+// flags every `mycelial add` subcommand has
+const RESERVED: [&str; 3] = ["display_name", "check", "save_anyway"];
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Kind {
+    Source,
+    Destination,
+}
+
+impl Kind {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Kind::Source => "source",
+            Kind::Destination => "destination",
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum FieldType {
+    #[default]
+    String,
+    Bool,
+    Integer,
+    Choice,
+    /// path to a file that must exist
+    File,
+    /// comma separated `host:port` list
+    Brokers,
+    /// connection url, prompted for part by part
+    Url,
+    /// Snowflake account identifier, or account url
+    Account,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct Registry {
+    pub connectors: Vec<Connector>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct Connector {
+    pub kind: Kind,
+    /// name of the `mycelial add` subcommand
+    pub name: String,
+    /// shown in the "what type of source" menus
+    pub label: String,
+    /// `type` of the config entry
+    #[serde(rename = "type")]
+    pub section_type: String,
+    /// default display name
+    pub display_name: String,
+    /// what `mycelial config list` shows, `{field}` is replaced by its value
+    #[serde(default)]
+    pub target: String,
+    /// left out of menus, but offered by `mycelial add` and validated
+    #[serde(default)]
+    pub hidden: bool,
+    /// how `--check` and `mycelial config test` reach the connector
+    #[serde(default)]
+    pub check: Option<Check>,
+    /// how `mycelial preview` reads sample rows of a source
+    #[serde(default)]
+    pub preview: Option<Preview>,
+    #[serde(default)]
+    pub fields: Vec<Field>,
+}
+
+/// Sample rows of a source, read from the path or url in `field`.
+#[derive(Deserialize, Debug)]
+pub(crate) struct Preview {
+    #[serde(rename = "type")]
+    pub preview_type: PreviewType,
+    pub field: String,
+    /// field holding the query, for databases
+    #[serde(default)]
+    pub query: Option<String>,
+    /// field holding the sheets, for workbooks
+    #[serde(default)]
+    pub sheets: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PreviewType {
+    Sqlite,
+    Postgres,
+    Mysql,
+    Excel,
+    /// the first lines of a file
+    File,
+}
+
+/// Connectivity check of a connector, run on the value of `field`.
+#[derive(Deserialize, Debug)]
+pub(crate) struct Check {
+    #[serde(rename = "type")]
+    pub check_type: CheckType,
+    pub field: String,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum CheckType {
+    /// opens the database file
+    Sqlite,
+    /// logs in with the connection url
+    Postgres,
+    Mysql,
+    /// connects to one of the brokers
+    Kafka,
+    /// reads the file, or for destinations checks it can be written
+    File,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct Field {
+    pub name: String,
+    /// `mycelial add` flag, the name with dashes unless set
+    #[serde(default)]
+    pub flag: Option<String>,
+    pub prompt: String,
+    #[serde(default)]
+    pub help: String,
+    #[serde(default, rename = "type")]
+    pub field_type: FieldType,
+    #[serde(default)]
+    pub choices: Vec<String>,
+    /// used when the field is not given
+    #[serde(default)]
+    pub default: Option<String>,
+    /// only pre-fills the prompt
+    #[serde(default)]
+    pub example: Option<String>,
+    #[serde(default)]
+    pub secret: bool,
+    #[serde(default = "required")]
+    pub required: bool,
+    #[serde(default)]
+    pub min: Option<i64>,
+    /// a bool answered by typing `true` or `false` instead of y/n
+    #[serde(default)]
+    pub typed: bool,
+    /// only asked for, and only required, when another field has one of
+    /// the listed values
+    #[serde(default)]
+    pub when: Option<Condition>,
+    /// asked for but not stored, its value follows from the fields that
+    /// depend on it
+    #[serde(default)]
+    pub prompt_only: bool,
+    #[serde(default)]
+    pub url: Option<UrlSpec>,
+    /// offers what is in the source instead of asking for free text
+    #[serde(default)]
+    pub introspect: Option<Introspect>,
+}
+
+fn required() -> bool {
+    true
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct Condition {
+    pub field: String,
+    pub one_of: Vec<String>,
+    /// names the condition in messages, such as "SASL authentication"
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+/// How the parts of a connection url are prompted for.
+#[derive(Deserialize, Debug)]
+pub(crate) struct UrlSpec {
+    pub schemes: Vec<String>,
+    /// prefix of the username and port prompts, such as "Postgres"
+    pub label: String,
+    pub user: String,
+    pub host: String,
+    pub port: String,
+    pub database: String,
+    /// optional query parameters
+    #[serde(default)]
+    pub params: Vec<UrlParam>,
+}
+
+/// Where a prompt finds what it offers.
+#[derive(Deserialize, Debug)]
+pub(crate) struct Introspect {
+    #[serde(rename = "type")]
+    pub introspect_type: IntrospectType,
+    /// field holding the path of the database or workbook
+    pub path: String,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum IntrospectType {
+    /// builds a query from the tables of a SQLite database
+    Sqlite,
+    /// picks sheets of an Excel workbook
+    Excel,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct UrlParam {
+    pub name: String,
+    pub prompt: String,
+    #[serde(default, rename = "type")]
+    pub param_type: FieldType,
+}
+
+/// A cross-field rule broken by a config entry, see [`Connector::conflicts`].
+pub(crate) enum Conflict<'a> {
+    /// fields required while `condition` holds are missing
+    Missing(Vec<&'a Field>, &'a Condition),
+    /// none of the fields that select an option of a prompt-only choice is set
+    NoneOf(Vec<&'a Field>),
+    /// fields selecting two different options are both set
+    Both(&'a Field, &'a Field),
+    /// a field is set without the field selecting its option
+    Requires(&'a Field, &'a Field),
+}
+
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+/// The connectors the CLI knows about, parsed on first use: those of
+/// [`installed`] when it was called first, the built-in ones otherwise.
+pub(crate) fn registry() -> &'static Registry {
+    REGISTRY.get_or_init(builtin)
+}
+
+/// The connectors of the installed daemon, or the built-in ones when there
+/// is no daemon or it can't describe them. The daemon is only run the first
+/// time, and not at all when the registry is already loaded, so commands
+/// call this before anything else reads the registry.
+pub(crate) fn installed() -> &'static Registry {
+    REGISTRY.get_or_init(|| {
+        let Some(path) = daemon_path() else {
+            return builtin();
+        };
+        // `None` if the daemon is too old to describe its connectors
+        match crate::ask_binary(&path, DESCRIBE_FLAG).and_then(|json| match json {
+            Some(json) => Registry::from_json(&json).map(Some),
+            None => Ok(None),
+        }) {
+            Ok(Some(registry)) => return registry,
+            Ok(None) => {}
+            // the output isn't set up yet while `add` is parsed, so this
+            // goes to stderr whatever the format
+            Err(error) => eprintln!(
+                "{}",
+                format!(
+                    "warning: {}: {}, using the built-in connectors",
+                    path.display(),
+                    error
+                )
+                .yellow()
+            ),
+        }
+        builtin()
+    })
+}
+
+fn builtin() -> Registry {
+    Registry::parse(BUILTIN).expect("invalid built-in connectors")
+}
+
+// the installed daemon, `MYCELIAL_DAEMON` overrides where it is looked for
+fn daemon_path() -> Option<PathBuf> {
+    match std::env::var_os(DAEMON_ENV) {
+        Some(path) => Some(PathBuf::from(path)),
+        None => Some(PathBuf::from(INSTALLED_DAEMON)).filter(|path| path.is_file()),
+    }
+}
+
+impl Registry {
+    /// Parses descriptors in the format of `connectors.toml`.
+    pub(crate) fn parse(descriptors: &str) -> Result<Registry, String> {
+        toml::from_str::<Registry>(descriptors)
+            .map_err(|error| format!("invalid connector descriptors: {}", error))?
+            .checked()
+    }
+
+    /// Parses the JSON printed by `myceliald --describe-connectors`.
+    pub(crate) fn from_json(descriptors: &str) -> Result<Registry, String> {
+        serde_json::from_str::<Registry>(descriptors)
+            .map_err(|error| format!("invalid connector descriptors: {}", error))?
+            .checked()
+    }
+
+    // fills in the flags and checks what serde can't
+    fn checked(mut self) -> Result<Registry, String> {
+        for connector in self.connectors.iter_mut() {
+            let names: Vec<String> = connector.fields.iter().map(|f| f.name.clone()).collect();
+            let mut used = Vec::new();
+            if let Some(check) = &connector.check {
+                used.push(("check", &check.field));
+            }
+            if let Some(preview) = &connector.preview {
+                used.push(("preview", &preview.field));
+                used.extend(preview.query.iter().map(|query| ("preview", query)));
+                used.extend(preview.sheets.iter().map(|sheets| ("preview", sheets)));
+            }
+            if let Some((user, field)) = used.iter().find(|(_, field)| !names.contains(field)) {
+                return Err(format!(
+                    "{} of {} `{}` uses an unknown field `{}`",
+                    user, connector.kind, connector.name, field
+                ));
+            }
+            for field in connector.fields.iter_mut() {
+                if field.flag.is_none() {
+                    field.flag = Some(field.name.replace('_', "-"));
+                }
+                let problem = if RESERVED.contains(&field.name.as_str()) {
+                    Some("clashes with a `mycelial add` flag")
+                } else if field.field_type == FieldType::Url && field.url.is_none() {
+                    Some("is missing its `url` settings")
+                } else if field.field_type == FieldType::Choice && field.choices.is_empty() {
+                    Some("has no choices")
+                } else if field
+                    .introspect
+                    .as_ref()
+                    .is_some_and(|introspect| !names.contains(&introspect.path))
+                {
+                    Some("introspects an unknown field")
+                } else if let Some(condition) = &field.when {
+                    (!names.contains(&condition.field)).then_some("depends on an unknown field")
+                } else {
+                    None
+                };
+                if let Some(problem) = problem {
+                    return Err(format!(
+                        "field `{}` of {} `{}` {}",
+                        field.name, connector.kind, connector.name, problem
+                    ));
+                }
+            }
+        }
+        if self.connectors.is_empty() {
+            return Err("no connectors described".into());
+        }
+        Ok(self)
+    }
+
+    /// Connectors offered by `mycelial add`.
+    pub(crate) fn of_kind(&self, kind: Kind) -> impl Iterator<Item = &Connector> {
+        self.connectors
+            .iter()
+            .filter(move |connector| connector.kind == kind)
+    }
+
+    /// Connectors offered in menus.
+    pub(crate) fn visible(&self, kind: Kind) -> impl Iterator<Item = &Connector> {
+        self.of_kind(kind).filter(|connector| !connector.hidden)
+    }
+
+    /// Looks up a connector by the `type` of a config entry.
+    pub(crate) fn find(&self, kind: Kind, section_type: &str) -> Option<&Connector> {
+        self.connectors
+            .iter()
+            .find(|connector| connector.kind == kind && connector.section_type == section_type)
+    }
+
+    /// Looks up a connector by its `mycelial add` subcommand name.
+    pub(crate) fn by_name(&self, kind: Kind, name: &str) -> Option<&Connector> {
+        self.of_kind(kind).find(|connector| connector.name == name)
+    }
+}
+
+impl Field {
+    pub(crate) fn flag(&self) -> &str {
+        self.flag.as_deref().unwrap_or(&self.name)
+    }
+
+    /// What the prompt offers when there is no current value.
+    pub(crate) fn suggestion(&self) -> Option<String> {
+        self.default.clone().or_else(|| self.example.clone())
+    }
+
+    /// True if the field may be left out of a config entry.
+    pub(crate) fn is_optional(&self) -> bool {
+        !self.required || self.when.is_some()
+    }
+
+    /// True for fields holding text, which may contain secret references.
+    pub(crate) fn is_text(&self) -> bool {
+        !matches!(self.field_type, FieldType::Bool | FieldType::Integer)
+    }
+}
+
+impl Connector {
+    pub(crate) fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// Fields written to the config, in order.
+    pub(crate) fn stored_fields(&self) -> impl Iterator<Item = &Field> {
+        self.fields.iter().filter(|field| !field.prompt_only)
+    }
+
+    /// Value of a field in `table`, falling back to its default. Prompt-only
+    /// choices not in `table` are inferred from the fields that depend on them.
+    pub(crate) fn value_of(&self, name: &str, table: &toml::Table) -> String {
+        match table.get(name) {
+            Some(toml::Value::String(value)) => return value.clone(),
+            Some(value) => return value.to_string(),
+            None => {}
+        }
+        match self.field(name) {
+            Some(field) if field.prompt_only => {
+                let chosen = self.chosen(field, table);
+                match chosen.first() {
+                    Some(choice) => choice.to_string(),
+                    None => field.default.clone().unwrap_or_default(),
+                }
+            }
+            Some(field) => field.default.clone().unwrap_or_default(),
+            None => String::new(),
+        }
+    }
+
+    /// True if `field` applies, given the other values in `table`.
+    pub(crate) fn applies(&self, field: &Field, table: &toml::Table) -> bool {
+        match &field.when {
+            Some(condition) => condition
+                .one_of
+                .contains(&self.value_of(&condition.field, table)),
+            None => true,
+        }
+    }
+
+    // required fields that only apply when `choice` is `option`
+    fn option_fields<'a>(&'a self, choice: &Field, option: &str) -> Vec<&'a Field> {
+        self.fields
+            .iter()
+            .filter(|field| field.required)
+            .filter(|field| match &field.when {
+                Some(condition) => {
+                    condition.field == choice.name && condition.one_of.iter().any(|o| o == option)
+                }
+                None => false,
+            })
+            .collect()
+    }
+
+    // options of a prompt-only choice whose required fields are set in `table`
+    fn chosen<'a>(&'a self, choice: &'a Field, table: &toml::Table) -> Vec<&'a str> {
+        choice
+            .choices
+            .iter()
+            .map(String::as_str)
+            .filter(|option| {
+                self.option_fields(choice, option)
+                    .iter()
+                    .any(|field| table.contains_key(&field.name))
+            })
+            .collect()
+    }
+
+    /// Checks the rules between fields: required fields of conditions that
+    /// hold, and exactly one option of every prompt-only choice.
+    pub(crate) fn conflicts(&self, table: &toml::Table) -> Vec<Conflict<'_>> {
+        let mut conflicts = Vec::new();
+        for choice in self.fields.iter().filter(|field| field.prompt_only) {
+            let chosen = self.chosen(choice, table);
+            let selecting: Vec<&Field> = choice
+                .choices
+                .iter()
+                .filter_map(|option| self.option_fields(choice, option).into_iter().next())
+                .collect();
+            match chosen.as_slice() {
+                [] => conflicts.push(Conflict::NoneOf(selecting)),
+                [_] => {}
+                [first, second, ..] => {
+                    let set = |option: &str| {
+                        self.option_fields(choice, option)
+                            .into_iter()
+                            .find(|field| table.contains_key(&field.name))
+                            .unwrap()
+                    };
+                    conflicts.push(Conflict::Both(set(first), set(second)));
+                }
+            }
+            if chosen.len() > 1 {
+                continue;
+            }
+            // fields of options other than the chosen one
+            for field in self.fields.iter().filter(|f| table.contains_key(&f.name)) {
+                let condition = match &field.when {
+                    Some(condition) if condition.field == choice.name => condition,
+                    _ => continue,
+                };
+                let active = chosen
+                    .first()
+                    .map(|option| condition.one_of.iter().any(|o| o == option))
+                    .unwrap_or(false);
+                if !active {
+                    if let Some(selecting) =
+                        self.option_fields(choice, &condition.one_of[0]).first()
+                    {
+                        conflicts.push(Conflict::Requires(field, selecting));
+                    }
+                }
+            }
+        }
+        let mut missing: Vec<(&Condition, Vec<&Field>)> = Vec::new();
+        for field in self.stored_fields().filter(|field| field.required) {
+            let condition = match &field.when {
+                Some(condition) => condition,
+                None => continue,
+            };
+            let on_choice = self
+                .field(&condition.field)
+                .map(|field| field.prompt_only)
+                .unwrap_or(false);
+            if on_choice || table.contains_key(&field.name) || !self.applies(field, table) {
+                continue;
+            }
+            match missing
+                .iter_mut()
+                .find(|(c, _)| std::ptr::eq(*c, condition))
+            {
+                Some((_, fields)) => fields.push(field),
+                None => missing.push((condition, vec![field])),
+            }
+        }
+        for (condition, fields) in missing {
+            conflicts.push(Conflict::Missing(fields, condition));
+        }
+        conflicts
+    }
+
+    /// Describes the condition, for messages such as "`x` is required with ...".
+    pub(crate) fn describe(&self, condition: &Condition, table: &toml::Table) -> String {
+        match &condition.label {
+            Some(label) => format!("with {}", label),
+            None => format!(
+                "when `{}` is `{}`",
+                condition.field,
+                self.value_of(&condition.field, table)
+            ),
+        }
+    }
+
+    /// Fills in the `target` template with the values of an entry.
+    pub(crate) fn target(&self, table: &toml::Table) -> String {
+        let mut target = self.target.clone();
+        for field in self.stored_fields() {
+            let pattern = format!("{{{}}}", field.name);
+            if !target.contains(&pattern) {
+                continue;
+            }
+            let value = self.value_of(&field.name, table);
+            let value = match field.field_type {
+                FieldType::Url => connection::url_target(&value),
+                _ => value,
+            };
+            target = target.replace(&pattern, &value);
+        }
+        target
+    }
+}
+
+/// Joins names as "a, b and c".
+pub(crate) fn join(names: &[String]) -> String {
+    match names {
+        [] => String::new(),
+        [name] => name.clone(),
+        [init @ .., last] => format!("{} and {}", init.join(", "), last),
+    }
+}
@@ -0,0 +1,105 @@
+//! Failures scripts need to tell apart, each exiting with its own code.
+//!
+//! | code | failure |
+//! |------|---------|
+//! | 1    | anything else |
+//! | 2    | invalid command line arguments |
+//! | 3    | missing or invalid config or answers file |
+//! | 4    | download of a release failed |
+//! | 5    | downloaded archive couldn't be unpacked |
+//! | 6    | daemon or control plane binary missing |
+//! | 7    | daemon or control plane failed to start |
+//! | 8    | installing or controlling the system service failed |
+//! | 9    | a prompt couldn't be asked or answered |
+//! | 10   | no release for this OS or architecture |
+//! | 11   | config file couldn't be written |
+//! | 12   | a confirmation was declined |
+//! | 13   | a connection test or preview couldn't reach a source or destination |
+//! | 14   | a secret isn't in the vault |
+//! | 130  | cancelled with Ctrl-C |
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    /// arguments clap can't check
+    Usage(String),
+    Config(String),
+    Download(String),
+    Archive(String),
+    MissingBinary(String),
+    Process(String),
+    Service(String),
+    Prompt(String),
+    Unsupported(String),
+    Save(String),
+    /// a confirmation needed to go on was answered no
+    Declined(String),
+    /// a source or destination couldn't be reached or read
+    Connection(String),
+    Secret(String),
+    Cancelled,
+}
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Usage(_) => 2,
+            Error::Config(_) => 3,
+            Error::Download(_) => 4,
+            Error::Archive(_) => 5,
+            Error::MissingBinary(_) => 6,
+            Error::Process(_) => 7,
+            Error::Service(_) => 8,
+            Error::Prompt(_) => 9,
+            Error::Unsupported(_) => 10,
+            Error::Save(_) => 11,
+            Error::Declined(_) => 12,
+            Error::Connection(_) => 13,
+            Error::Secret(_) => 14,
+            Error::Cancelled => 130,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Usage(message)
+            | Error::Config(message)
+            | Error::Download(message)
+            | Error::Archive(message)
+            | Error::MissingBinary(message)
+            | Error::Process(message)
+            | Error::Service(message)
+            | Error::Prompt(message)
+            | Error::Unsupported(message)
+            | Error::Save(message)
+            | Error::Declined(message)
+            | Error::Connection(message)
+            | Error::Secret(message) => write!(f, "{}", message),
+            Error::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<dialoguer::Error> for Error {
+    fn from(error: dialoguer::Error) -> Error {
+        match error {
+            dialoguer::Error::IO(error) if error.kind() == std::io::ErrorKind::Interrupted => {
+                Error::Cancelled
+            }
+            dialoguer::Error::IO(error) => Error::Prompt(format!("could not prompt: {}", error)),
+        }
+    }
+}
+
+/// The exit code for any error returned by the CLI, 1 for untyped ones.
+pub fn exit_code(error: &(dyn std::error::Error + 'static)) -> i32 {
+    match error.downcast_ref::<Error>() {
+        Some(error) => error.exit_code(),
+        None => 1,
+    }
+}
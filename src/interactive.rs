@@ -0,0 +1,39 @@
+//! Whether the CLI may prompt, set once from `--non-interactive` and `--yes`.
+//!
+//! Without prompts every question is answered by a value passed on the
+//! command line, by its default, or fails naming what to pass instead.
+
+use crate::{Error, Result};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Interactive,
+    /// never prompt
+    NonInteractive,
+    /// never prompt, confirmations are answered yes
+    AssumeYes,
+}
+
+static MODE: AtomicU8 = AtomicU8::new(Mode::Interactive as u8);
+
+/// Sets how prompts are answered for the rest of the process.
+pub fn set_mode(mode: Mode) {
+    MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+pub(crate) fn mode() -> Mode {
+    match MODE.load(Ordering::Relaxed) {
+        0 => Mode::Interactive,
+        1 => Mode::NonInteractive,
+        _ => Mode::AssumeYes,
+    }
+}
+
+/// Fails when prompts aren't allowed, `instead` tells what to pass.
+pub fn ensure(what: &str, instead: &str) -> Result<()> {
+    match mode() {
+        Mode::Interactive => Ok(()),
+        _ => Err(Error::Prompt(format!("{} needs to prompt, {}", what, instead)).into()),
+    }
+}
@@ -0,0 +1,170 @@
+//! Reads the structure of a source, so prompts can offer its tables and
+//! sheets instead of asking for free text.
+
+use calamine::{Data, Reader};
+use rusqlite::{Connection, OpenFlags};
+use std::path::Path;
+
+// rows shown in a sheet preview, after the header
+const PREVIEW_ROWS: usize = 3;
+
+/// A table and its columns.
+pub(crate) struct Table {
+    pub name: String,
+    pub columns: Vec<String>,
+}
+
+fn open_sqlite(path: &str) -> Result<Connection, String> {
+    Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|error| format!("can't open `{}`: {}", path, error))
+}
+
+/// Lists the tables of a SQLite database, `None` if the file doesn't exist.
+pub(crate) fn sqlite_tables(path: &str) -> Result<Option<Vec<Table>>, String> {
+    if !Path::new(path).is_file() {
+        return Ok(None);
+    }
+    let connection = open_sqlite(path)?;
+    let read_error = |error: rusqlite::Error| format!("can't read `{}`: {}", path, error);
+    let names: Vec<String> = connection
+        .prepare(
+            "select name from sqlite_master \
+             where type in ('table', 'view') and name not like 'sqlite_%' order by name",
+        )
+        .and_then(|mut statement| {
+            statement
+                .query_map([], |row| row.get(0))?
+                .collect::<Result<_, _>>()
+        })
+        .map_err(read_error)?;
+    let mut tables = Vec::with_capacity(names.len());
+    for name in names {
+        let columns = connection
+            .prepare("select name from pragma_table_info(?1) order by cid")
+            .and_then(|mut statement| {
+                statement
+                    .query_map([&name], |row| row.get(0))?
+                    .collect::<Result<_, _>>()
+            })
+            .map_err(read_error)?;
+        tables.push(Table { name, columns });
+    }
+    Ok(Some(tables))
+}
+
+/// Checks that `query` is valid for the database, without running it.
+pub(crate) fn sqlite_explain(path: &str, query: &str) -> Result<(), String> {
+    let connection = open_sqlite(path)?;
+    connection
+        .prepare(&format!("explain {}", query))
+        .and_then(|mut statement| statement.query([]).map(|_| ()))
+        .map_err(|error| error.to_string())
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Builds the query reading `columns` of `table`, all of them when
+/// `columns` is empty.
+pub(crate) fn select_query(table: &str, columns: &[&str]) -> String {
+    let columns = match columns {
+        [] => "*".to_string(),
+        columns => columns
+            .iter()
+            .map(|column| quote(column))
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    format!("select {} from {}", columns, quote(table))
+}
+
+/// A worksheet: its header, first rows, and the first cell that strict mode
+/// would reject.
+pub(crate) struct Sheet {
+    pub name: String,
+    /// data rows, without the header
+    pub rows: usize,
+    pub header: Vec<String>,
+    pub preview: Vec<Vec<String>>,
+    pub mismatch: Option<String>,
+}
+
+// cell types strict mode tells apart
+fn cell_type(cell: &Data) -> Option<&'static str> {
+    match cell {
+        Data::Int(_) | Data::Float(_) => Some("number"),
+        Data::String(_) => Some("text"),
+        Data::Bool(_) => Some("boolean"),
+        Data::DateTime(_) | Data::DateTimeIso(_) | Data::DurationIso(_) => Some("date"),
+        Data::Error(_) => Some("error"),
+        Data::Empty => None,
+    }
+}
+
+// spreadsheet name of a column, 0 is `A`
+fn column_name(mut index: usize) -> String {
+    let mut name = String::new();
+    loop {
+        name.insert(0, (b'A' + (index % 26) as u8) as char);
+        if index < 26 {
+            return name;
+        }
+        index = index / 26 - 1;
+    }
+}
+
+// the first cell whose type differs from the first value of its column
+fn first_mismatch(range: &calamine::Range<Data>) -> Option<String> {
+    let (start_row, start_column) = range.start().unwrap_or((0, 0));
+    let mut types: Vec<Option<&'static str>> = vec![None; range.width()];
+    for (row_index, row) in range.rows().enumerate().skip(1) {
+        for (column, cell) in row.iter().enumerate() {
+            let found = match cell_type(cell) {
+                Some(found) => found,
+                None => continue,
+            };
+            match types[column] {
+                None => types[column] = Some(found),
+                Some(expected) if expected != found => {
+                    return Some(format!(
+                        "{}{}, `{}` in a {} column",
+                        column_name(start_column as usize + column),
+                        start_row as usize + row_index + 1,
+                        cell,
+                        expected
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    None
+}
+
+/// Lists the sheets of an Excel workbook, `None` if the file doesn't exist.
+pub(crate) fn excel_sheets(path: &str) -> Result<Option<Vec<Sheet>>, String> {
+    if !Path::new(path).is_file() {
+        return Ok(None);
+    }
+    let mut workbook = calamine::open_workbook_auto(path)
+        .map_err(|error| format!("can't open `{}`: {}", path, error))?;
+    let mut sheets = Vec::new();
+    for name in workbook.sheet_names() {
+        let range = workbook
+            .worksheet_range(&name)
+            .map_err(|error| format!("can't read sheet `{}` of `{}`: {}", name, path, error))?;
+        let mut rows = range
+            .rows()
+            .map(|row| row.iter().map(ToString::to_string).collect::<Vec<_>>());
+        let header = rows.next().unwrap_or_default();
+        sheets.push(Sheet {
+            name,
+            rows: range.height().saturating_sub(1),
+            header,
+            preview: rows.take(PREVIEW_ROWS).collect(),
+            mismatch: first_mismatch(&range),
+        });
+    }
+    Ok(Some(sheets))
+}
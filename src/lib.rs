@@ -1,85 +1,288 @@
 use colored::*;
 use flate2::read::GzDecoder;
 use indicatif::{ProgressBar, ProgressState, ProgressStyle};
-use std::cmp::min;
+use percent_encoding::percent_decode_str;
 use std::fmt;
 use std::fs::{self, read_to_string, remove_file, File};
-use std::io::Write;
-use std::path::Path;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tar::Archive;
 use uuid::Uuid;
 extern crate dirs;
+mod add;
+mod answers;
+mod check;
 mod config;
-use config::Config as Configuration;
-use dialoguer::{theme::ColorfulTheme, Confirm, FuzzySelect, Input, Password};
+mod connection;
+mod connector;
+mod error;
+mod interactive;
+mod introspect;
+pub mod output;
+mod preview;
+pub mod prompt;
+mod quickstart;
+mod release;
+mod secrets;
+mod validate;
+mod vault;
+pub use add::{add_destination_from_args, add_source_from_args, DestinationArgs, SourceArgs};
+use config::{Config as Configuration, Pipeline, Section};
+use connection::{ConnectionUrl, TlsParams};
+use connector::{Connector, Field, FieldType, IntrospectType, Kind};
+pub use error::{exit_code, Error};
+pub use interactive::{ensure, set_mode, Mode};
+pub use prompt::Prompter;
+use prompt::Text;
+pub use release::{Release, Version};
+pub use vault::{get_secret, list_secrets, remove_secret, set_secret};
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
+// how long a binary has to answer [`ask_binary`]
+const ASK_TIMEOUT: Duration = Duration::from_secs(5);
+
 enum Executable {
     ControlPlane,
     Daemon,
 }
 
+/// Downloads the binaries, of `release` when given, and creates a config,
+/// from the prompts or, without a terminal, from an answers file (`-` reads
+/// it from stdin).
+#[allow(clippy::too_many_arguments)]
 pub async fn init(
+    p: &mut dyn Prompter,
     daemon: bool,
     control_plane: bool,
     config_file_name: String,
     endpoint: Option<String>,
     token: Option<String>,
+    answers: Option<String>,
+    release: Option<Release>,
 ) -> Result<()> {
-    println!("{}", "Initializing Mycelial".green());
-    download_binaries(daemon, control_plane).await?;
-    println!(
-        "{}",
-        "Create a config file by answering the following questions.".green()
-    );
-    create_config(config_file_name, None, None, endpoint, token).await?;
+    if let Some(answers_file_name) = answers {
+        // nothing is downloaded or written unless every answer is there
+        let contents = match answers_file_name.as_str() {
+            "-" => std::io::read_to_string(std::io::stdin()),
+            file_name => read_to_string(file_name),
+        }
+        .map_err(|error| {
+            Error::Config(format!(
+                "could not read answers file `{}`: {}",
+                answers_file_name, error
+            ))
+        })?;
+        let config = answers::config(&answers_file_name, &contents, endpoint, token)?;
+        if Path::new(&config_file_name).exists() {
+            return Err(Error::Config(format!(
+                "config file `{}` already exists",
+                config_file_name
+            ))
+            .into());
+        }
+        output::say("Initializing Mycelial".green());
+        download_binaries(daemon, control_plane, release).await?;
+        config.save(&config_file_name).map_err(|error| {
+            Error::Save(format!(
+                "could not write config file `{}`: {}",
+                config_file_name, error
+            ))
+        })?;
+        output::say(
+            format!(
+                "created {} with {} source(s), {} destination(s) and {} pipeline(s)",
+                config_file_name,
+                config.source_names().len(),
+                config.destination_names().len(),
+                config.pipelines().len()
+            )
+            .green(),
+        );
+        report_config(&config_file_name, &config);
+        return Ok(());
+    }
+    ensure(
+        "`mycelial init`",
+        "pass --answers with a file holding every answer",
+    )?;
+    output::say("Initializing Mycelial".green());
+    download_binaries(daemon, control_plane, release).await?;
+    output::say("Create a config file by answering the following questions.".green());
+    create_config(p, config_file_name, None, None, endpoint, token).await?;
     Ok(())
 }
 
-pub async fn start(daemon: bool, control_plane: bool, config_file_name: String) -> Result<()> {
+/// Starts the daemon and/or the control plane, asking for the control plane
+/// token unless `token` is given.
+pub async fn start(
+    p: &mut dyn Prompter,
+    daemon: bool,
+    control_plane: bool,
+    config_file_name: String,
+    token: Option<String>,
+) -> Result<()> {
+    if control_plane && token.is_none() {
+        ensure(
+            "starting the control plane",
+            "pass --token with its security token",
+        )?;
+    }
     destroy(daemon, control_plane).await?;
     if control_plane {
         if !can_start_server() {
-            println!(
-                "{}",
-                "Missing control plane binary. You must run `mycelial init --local` before `mycelial start`".red()
-            );
-            return Ok(());
+            return Err(Error::MissingBinary(
+                "missing control plane binary, run `mycelial init --local` before `mycelial start`"
+                    .to_string(),
+            )
+            .into());
         }
-        start_server().await?;
+        start_server(p, token).await?;
     }
     if daemon {
-        if !can_start_client(&config_file_name) {
-            println!(
-                "{}",
-                "Missing daemon binary or config file. You must run `mycelial init --local` before `mycelial start`".red()
-            );
-            return Ok(());
+        if !Path::new("myceliald").exists() {
+            return Err(Error::MissingBinary(
+                "missing daemon binary, run `mycelial init --local` before `mycelial start`"
+                    .to_string(),
+            )
+            .into());
+        }
+        if !Path::new(&config_file_name).exists() {
+            return Err(Error::Config(format!(
+                "missing config file `{}`, run `mycelial init --local` before `mycelial start`",
+                config_file_name
+            ))
+            .into());
         }
-        start_client(config_file_name).await?;
+        start_client(p, config_file_name).await?;
     }
     Ok(())
 }
 
+/// Sets up a demo pipeline: downloads the binaries that are missing, writes
+/// a sample database and a config replicating it, then starts the control
+/// plane and the daemon with a generated token.
+pub async fn quickstart(
+    p: &mut dyn Prompter,
+    config_file_name: &str,
+    hello_world: bool,
+    no_start: bool,
+) -> Result<()> {
+    if Path::new(config_file_name).exists() {
+        return Err(Error::Config(format!(
+            "`{}` already exists, pass --config to write the quickstart config to another file",
+            config_file_name
+        ))
+        .into());
+    }
+    output::say("Setting up the Mycelial quickstart".green());
+    if !hello_world {
+        let rows = quickstart::sample_database(quickstart::DATABASE)?;
+        output::say(format!(
+            "sample database `{}` has {} orders",
+            quickstart::DATABASE,
+            rows
+        ));
+        output::set(
+            "sample_database",
+            serde_json::json!({"path": quickstart::DATABASE, "rows": rows}),
+        );
+    }
+    let token = Uuid::new_v4().simple().to_string();
+    let config = quickstart::config(&token, hello_world);
+    config.save(config_file_name).map_err(|error| {
+        Error::Save(format!(
+            "could not write config file `{}`: {}",
+            config_file_name, error
+        ))
+    })?;
+    output::say(format!("wrote `{}`", config_file_name));
+    report_config(config_file_name, &config);
+    output::set("token", &token);
+    let pipeline = &config.pipelines()[0];
+    let pipeline = format!(
+        "`{}`: {} -> {}",
+        pipeline.name,
+        pipeline.source,
+        pipeline.destinations.join(", ")
+    );
+    if no_start {
+        output::say("");
+        output::say(format!("pipeline        {}", pipeline));
+        output::say(format!("token           {}", token));
+        output::say(format!(
+            "Run `mycelial start --config {}` with the token above, then create the pipeline in the control plane at {}.",
+            config_file_name,
+            quickstart::ENDPOINT
+        ));
+        return Ok(());
+    }
+    let (daemon, control_plane) = (!Path::new("myceliald").exists(), !can_start_server());
+    download_binaries(daemon, control_plane, None).await?;
+    destroy(true, true).await?;
+    start_server(p, Some(token.clone())).await?;
+    start_client(p, config_file_name.to_string()).await?;
+    output::say("");
+    output::say("Mycelial quickstart is running".green());
+    output::say(format!(
+        "control plane   {}, token {}",
+        quickstart::ENDPOINT,
+        token
+    ));
+    output::say(format!(
+        "pipeline        {}, create it in the control plane",
+        pipeline
+    ));
+    if hello_world {
+        output::say("messages        daemon.log");
+    } else {
+        output::say(format!(
+            "source data     {} (table `orders`)",
+            quickstart::DATABASE
+        ));
+        output::say(format!("replica         {}", quickstart::REPLICA));
+    }
+    output::say("logs            control_plane.log, daemon.log");
+    if !hello_world {
+        output::say(format!(
+            "Run `mycelial preview \"{}\" --config {}` to see the rows being replicated.",
+            config.source_names()[0],
+            config_file_name
+        ));
+    }
+    output::say("Run `mycelial destroy` to stop the control plane and the daemon.");
+    Ok(())
+}
+
 pub async fn destroy(daemon: bool, control_plane: bool) -> Result<()> {
+    output::set("killed", Vec::<serde_json::Value>::new());
     if daemon {
         let pids = get_pids(Executable::Daemon);
         for pid in pids {
-            let pid_int = pid.parse::<i32>().unwrap();
+            let pid_int = match pid.parse::<i32>() {
+                Ok(pid_int) => pid_int,
+                Err(_err) => {
+                    output::warn(format!("skipping invalid daemon pid `{}`", pid));
+                    continue;
+                }
+            };
             let result = nix::sys::signal::kill(
                 nix::unistd::Pid::from_raw(pid_int),
                 nix::sys::signal::SIGKILL,
             );
             match result {
                 Ok(_) => {
-                    println!("killed daemon pid {}", pid);
+                    output::say(format!("killed daemon pid {}", pid));
+                    output::push(
+                        "killed",
+                        serde_json::json!({"process": "daemon", "pid": pid_int}),
+                    );
                 }
                 Err(_err) => {
-                    eprintln!("error killing daemon pid {}", pid);
+                    output::warn(format!("error killing daemon pid {}", pid));
                 }
             }
         }
@@ -88,17 +291,27 @@ pub async fn destroy(daemon: bool, control_plane: bool) -> Result<()> {
     if control_plane {
         let pids = get_pids(Executable::ControlPlane);
         for pid in pids {
-            let pid_int = pid.parse::<i32>().unwrap();
+            let pid_int = match pid.parse::<i32>() {
+                Ok(pid_int) => pid_int,
+                Err(_err) => {
+                    output::warn(format!("skipping invalid control plane pid `{}`", pid));
+                    continue;
+                }
+            };
             let result = nix::sys::signal::kill(
                 nix::unistd::Pid::from_raw(pid_int),
                 nix::sys::signal::SIGKILL,
             );
             match result {
                 Ok(_) => {
-                    println!("killed control plane pid {}", pid);
+                    output::say(format!("killed control plane pid {}", pid));
+                    output::push(
+                        "killed",
+                        serde_json::json!({"process": "control_plane", "pid": pid_int}),
+                    );
                 }
                 Err(_err) => {
-                    eprintln!("error killing control plane pid {}", pid);
+                    output::warn(format!("error killing control plane pid {}", pid));
                 }
             }
         }
@@ -114,42 +327,47 @@ fn storage_path(config_file_name: &str) -> Option<String> {
     }
 }
 
-pub async fn reset(daemon: bool, control_plane: bool, config_file_name: &str) -> Result<()> {
-    let answer: bool = Confirm::with_theme(&ColorfulTheme::default())
-        .with_prompt("Are you sure you want to reset Mycelial?")
-        .interact()
-        .unwrap();
+pub async fn reset(
+    p: &mut dyn Prompter,
+    daemon: bool,
+    control_plane: bool,
+    config_file_name: &str,
+) -> Result<()> {
+    let answer = p.confirm("Are you sure you want to reset Mycelial?", None)?;
     if answer {
         if daemon {
-            let client_db_path =
-                storage_path(config_file_name).expect("Could not find config.toml");
-            let result = remove_file(&client_db_path);
-            match result {
-                Ok(_) => {
-                    println!("{}", format!("{} deleted", client_db_path).green());
-                }
-                Err(_error) => {
-                    println!("{}", format!("{} does not exists", client_db_path).yellow());
-                }
-            }
+            let client_db_path = storage_path(config_file_name).ok_or_else(|| {
+                Error::Config(format!(
+                    "could not read the daemon storage path from `{}`",
+                    config_file_name
+                ))
+            })?;
+            report_removal(&client_db_path);
         }
         if control_plane {
-            let result = remove_file("mycelial.db");
-            match result {
-                Ok(_) => {
-                    println!("{}", "mycelial.db deleted!".green());
-                }
-                Err(_error) => {
-                    println!("{}", "mycelial.db does not exist".yellow());
-                }
-            }
+            report_removal("mycelial.db");
         }
     } else {
-        println!("{}", "Reset cancelled".yellow());
+        output::warn("Reset cancelled");
+        output::set("cancelled", true);
     }
     Ok(())
 }
 
+// removes a database being reset, a missing one is only reported
+fn report_removal(path: &str) {
+    match remove_file(path) {
+        Ok(_) => {
+            output::say(format!("{} deleted", path).green());
+            output::push("deleted", path);
+        }
+        Err(_error) => {
+            output::say(format!("{} does not exist", path).yellow());
+            output::push("missing", path);
+        }
+    }
+}
+
 fn delete_pids_file(executable: Executable) -> Result<()> {
     let file_name = get_pid_file(&executable);
     let result = fs::remove_file(&file_name);
@@ -159,7 +377,7 @@ fn delete_pids_file(executable: Executable) -> Result<()> {
                 Executable::ControlPlane => "control plane",
                 Executable::Daemon => "daemon",
             };
-            println!("deleted {} pid file ({})", which, file_name);
+            output::say(format!("deleted {} pid file ({})", which, file_name));
         }
         Err(_error) => {
             // pids file (~/.mycelial) may not exist, so ignore errors
@@ -193,10 +411,12 @@ fn get_pids(executable: Executable) -> Vec<String> {
     pids
 }
 
+fn mycelial_dir() -> PathBuf {
+    dirs::home_dir().unwrap().join(".mycelial")
+}
+
 fn create_pid_file_dir() -> Result<()> {
-    let dir_name = format!("{}/.mycelial", dirs::home_dir().unwrap().display());
-    let path = Path::new(&dir_name);
-    fs::create_dir_all(path)?;
+    fs::create_dir_all(mycelial_dir())?;
     Ok(())
 }
 
@@ -211,602 +431,971 @@ fn save_pid(executable: Executable, pid: u32) -> Result<()> {
     Ok(())
 }
 
-pub async fn download_binaries(daemon: bool, control_plane: bool) -> Result<()> {
+/// Downloads the daemon and/or the control plane of `release`, or of the
+/// pinned release when it's `None`, and pins a release asked for.
+pub async fn download_binaries(
+    daemon: bool,
+    control_plane: bool,
+    release: Option<Release>,
+) -> Result<()> {
+    if !daemon && !control_plane {
+        return release::record(release.as_ref(), None, false, false);
+    }
+    let target = target()?;
+    let version = release::resolve(release.as_ref()).await?;
+    download_release(daemon, control_plane, target, release.as_ref(), version).await
+}
+
+/// Updates the daemon and/or the control plane like [`download_binaries`],
+/// asking before moving to another major version.
+pub async fn update(
+    p: &mut dyn Prompter,
+    daemon: bool,
+    control_plane: bool,
+    release: Option<Release>,
+) -> Result<()> {
+    let target = target()?;
+    let version = release::resolve(release.as_ref()).await?;
+    release::check_update(p, daemon, control_plane, version.as_ref())?;
+    download_release(daemon, control_plane, target, release.as_ref(), version).await?;
+    output::set(
+        "updated",
+        serde_json::json!({ "daemon": daemon, "control_plane": control_plane }),
+    );
+    output::say("Update complete");
+    Ok(())
+}
+
+async fn download_release(
+    daemon: bool,
+    control_plane: bool,
+    target: &str,
+    release: Option<&Release>,
+    version: Option<Version>,
+) -> Result<()> {
+    let name = match &version {
+        Some(version) => version.to_string(),
+        None => "latest".to_string(),
+    };
     if control_plane && daemon {
-        println!("Downloading and unarchiving control plane and daemon...");
+        output::say(format!(
+            "Downloading and unarchiving control plane and daemon {}...",
+            name
+        ));
     } else if control_plane {
-        println!("Downloading and unarchiving control plane...");
+        output::say(format!(
+            "Downloading and unarchiving control plane {}...",
+            name
+        ));
     } else if daemon {
-        println!("Downloading and unarchiving daemon...");
+        output::say(format!("Downloading and unarchiving daemon {}...", name));
     }
-    match std::env::consts::OS {
-        "linux" => match std::env::consts::ARCH {
-            "x86_64" => {
-                if control_plane {
-                    download_and_unarchive("https://github.com/mycelial/mycelial/releases/latest/download/server-x86_64-unknown-linux-gnu.tgz" , "server-x86_64-unknown-linux-gnu.tgz").await?;
-                }
-                if daemon {
-                    download_and_unarchive("https://github.com/mycelial/mycelial/releases/latest/download/myceliald-x86_64-unknown-linux-gnu.tgz", "myceliald-x86_64-unknown-linux-gnu.tgz").await?;
-                }
-            }
-            "aarch64" => {
-                if control_plane {
-                    download_and_unarchive("https://github.com/mycelial/mycelial/releases/latest/download/server-aarch64-unknown-linux-gnu.tgz" , "server-aarch64-unknown-linux-gnu.tgz").await?;
-                }
-                if daemon {
-                    download_and_unarchive("https://github.com/mycelial/mycelial/releases/latest/download/myceliald-aarch64-unknown-linux-gnu.tgz", "myceliald-aarch64-unknown-linux-gnu.tgz").await?;
-                }
-            }
-            "arm" => {
-                if control_plane {
-                    download_and_unarchive("https://github.com/mycelial/mycelial/releases/latest/download/server-arm-unknown-linux-gnueabihf.tgz" , "server-arm-unknown-linux-gnueabihf.tgz").await?;
-                }
-                if daemon {
-                    download_and_unarchive("https://github.com/mycelial/mycelial/releases/latest/download/myceliald-arm-unknown-linux-gnueabihf.tgz", "myceliald-arm-unknown-linux-gnueabihf.tgz").await?;
-                }
-            }
-            _ => {
-                panic!("Unsupported architecture");
-            }
-        },
-        "macos" => match std::env::consts::ARCH {
-            "x86_64" => {
-                if control_plane {
-                    download_and_unarchive("https://github.com/mycelial/mycelial/releases/latest/download/server-x86_64-apple-darwin.tgz", "server-x86_64-apple-darwin.tgz").await?;
-                }
-                if daemon {
-                    download_and_unarchive("https://github.com/mycelial/mycelial/releases/latest/download/myceliald-x86_64-apple-darwin.tgz", "myceliald-x86_64-apple-darwin.tgz").await?;
-                }
-            }
-            "aarch64" => {
-                if control_plane {
-                    download_and_unarchive("https://github.com/mycelial/mycelial/releases/latest/download/server-aarch64-apple-darwin.tgz", "server-aarch64-apple-darwin.tgz").await?;
-                }
-                if daemon {
-                    download_and_unarchive("https://github.com/mycelial/mycelial/releases/latest/download/myceliald-aarch64-apple-darwin.tgz", "myceliald-aarch64-apple-darwin.tgz").await?;
-                }
-            }
-            _ => {
-                panic!("Unsupported architecture");
-            }
-        },
-        _ => {
-            panic!("Unsupported OS");
+    output::set("version", &version);
+    for (wanted, binary) in [(control_plane, "server"), (daemon, "myceliald")] {
+        if wanted {
+            let asset = format!("{}-{}.tgz", binary, target);
+            download_and_unarchive(&release::url(version.as_ref(), &asset), &asset).await?;
         }
     }
-    Ok(())
+    release::record(release, version.as_ref(), daemon, control_plane)
 }
 
-async fn start_server() -> Result<()> {
-    println!("Starting Mycelial Control Plane...");
-    let server_log_file = File::create("control_plane.log")?;
-    let token = Password::with_theme(&ColorfulTheme::default())
-        .with_prompt("Security Token:")
-        .interact()
-        .unwrap();
+// the target triple of the released binaries for this host
+fn target() -> Result<&'static str> {
+    match (std::env::consts::OS, std::env::consts::ARCH) {
+        ("linux", "x86_64") => Ok("x86_64-unknown-linux-gnu"),
+        ("linux", "aarch64") => Ok("aarch64-unknown-linux-gnu"),
+        ("linux", "arm") => Ok("arm-unknown-linux-gnueabihf"),
+        ("macos", "x86_64") => Ok("x86_64-apple-darwin"),
+        ("macos", "aarch64") => Ok("aarch64-apple-darwin"),
+        _ => Err(unsupported_platform()),
+    }
+}
 
+fn unsupported_platform() -> Box<dyn std::error::Error + Send + Sync> {
+    Error::Unsupported(format!(
+        "no Mycelial release for {} on {}",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    ))
+    .into()
+}
+
+// asks for the security token when none is given
+async fn start_server(p: &mut dyn Prompter, token: Option<String>) -> Result<()> {
+    output::say("Starting Mycelial Control Plane...");
+    let token = match token {
+        Some(token) => token,
+        None => p.password(Text::new("Security Token:").flag("--token"))?,
+    };
+    let server_log_file = log_file("control_plane.log")?;
     let mut server_process = match std::process::Command::new("./server")
         .arg("--token")
         .arg(token)
         .stdin(Stdio::null())
-        .stdout(Stdio::from(
-            server_log_file.try_clone().expect("Could not clone file"),
-        ))
+        .stdout(Stdio::from(server_log_file.try_clone()?))
         .stderr(Stdio::from(server_log_file))
         .spawn()
     {
         Ok(process) => process,
-        Err(e) => panic!("failed to execute process: {}", e),
+        Err(e) => {
+            return Err(Error::Process(format!("could not start the control plane: {}", e)).into())
+        }
     };
     save_pid(Executable::ControlPlane, server_process.id())?;
     thread::sleep(Duration::from_secs(1));
     match server_process.try_wait() {
         Ok(Some(_status)) => {
-            println!("{}", "Mycelial Control Plane failed to start! ".red());
-            println!("{}", "check control_plane.log for more information".red());
+            return Err(Error::Process(
+                "Mycelial Control Plane failed to start, check control_plane.log for more information"
+                    .to_string(),
+            )
+            .into());
         }
         Ok(None) => {
-            println!(
-                "{}",
-                "Control Plane started on `http://localhost:7777`".green()
+            output::say("Control Plane started on `http://localhost:7777`".green());
+            output::push(
+                "started",
+                serde_json::json!({
+                    "process": "control_plane",
+                    "pid": server_process.id(),
+                    "endpoint": "http://localhost:7777",
+                    "log": "control_plane.log",
+                }),
             );
         }
         Err(e) => {
-            println!("error attempting to wait: {}", e);
+            output::warn(format!("error attempting to wait: {}", e));
         }
     }
     Ok(())
 }
 
-async fn start_client(config_file_name: String) -> Result<()> {
-    println!("Starting daemon with config file {}...", config_file_name);
-    let myceliald_log_file = File::create("daemon.log")?;
+async fn start_client(p: &mut dyn Prompter, config_file_name: String) -> Result<()> {
+    output::say(format!(
+        "Starting daemon with config file {}...",
+        config_file_name
+    ));
+    let resolved_config = secrets::resolve_config(p, &config_file_name, None).map_err(|error| {
+        format!(
+            "error resolving secrets in `{}`: {}",
+            config_file_name, error
+        )
+    })?;
+    // the resolved config is written to a private temporary directory, which
+    // is removed once the daemon has read it, or failed to
+    let (resolved_dir, daemon_config_file_name) = match &resolved_config {
+        Some(resolved) => {
+            let dir = secrets::PrivateDir::create()?;
+            let path = dir.path().join("config.toml");
+            secrets::write_private(&path, resolved)?;
+            (Some(dir), path)
+        }
+        None => (None, PathBuf::from(&config_file_name)),
+    };
+    let myceliald_log_file = log_file("daemon.log")?;
     let mut client_process = match std::process::Command::new("./myceliald")
         .arg("--config")
-        .arg(config_file_name)
+        .arg(daemon_config_file_name)
         .stdin(Stdio::null())
-        .stdout(Stdio::from(
-            myceliald_log_file
-                .try_clone()
-                .expect("Could not clone file"),
-        ))
+        .stdout(Stdio::from(myceliald_log_file.try_clone()?))
         .stderr(Stdio::from(myceliald_log_file))
         .spawn()
     {
         Ok(process) => process,
-        Err(e) => panic!("failed to execute process: {}", e),
+        Err(e) => return Err(Error::Process(format!("could not start the daemon: {}", e)).into()),
     };
     save_pid(Executable::Daemon, client_process.id())?;
     thread::sleep(Duration::from_secs(1));
+    drop(resolved_dir);
     match client_process.try_wait() {
         Ok(Some(_status)) => {
-            println!("{}", "daemon failed to start! ".red());
-            println!("{}", "check daemon.log for more information".red());
+            return Err(Error::Process(
+                "daemon failed to start, check daemon.log for more information".to_string(),
+            )
+            .into());
         }
         Ok(None) => {
-            println!("{}", "daemon started!".green());
+            output::say("daemon started!".green());
+            output::push(
+                "started",
+                serde_json::json!({
+                    "process": "daemon",
+                    "pid": client_process.id(),
+                    "config": config_file_name,
+                    "log": "daemon.log",
+                }),
+            );
         }
         Err(e) => {
-            println!("error attempting to wait: {}", e);
+            output::warn(format!("error attempting to wait: {}", e));
         }
     }
     Ok(())
 }
+// runs `path flag` and returns what it prints, `None` if it exits with an
+// error, as binaries too old to support `flag` do
+pub(crate) fn ask_binary(path: &Path, flag: &str) -> std::result::Result<Option<String>, String> {
+    let mut child = std::process::Command::new(path)
+        .arg(flag)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|error| format!("can't run `{}` ({})", path.display(), error))?;
+    // read on another thread, so a large output can't fill the pipe
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = thread::spawn(move || {
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).map(|_| output)
+    });
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() < ASK_TIMEOUT => thread::sleep(Duration::from_millis(20)),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("`{}` timed out", flag));
+            }
+            Err(error) => return Err(error.to_string()),
+        }
+    };
+    if !status.success() {
+        return Ok(None);
+    }
+    match reader.join() {
+        Ok(Ok(output)) => Ok(Some(output)),
+        _ => Err(format!("can't read the output of `{}`", flag)),
+    }
+}
+
+// the log file of a process being started
+fn log_file(file_name: &str) -> Result<File> {
+    File::create(file_name).map_err(|error| {
+        Error::Process(format!("could not create `{}`: {}", file_name, error)).into()
+    })
+}
+
 pub async fn download_and_unarchive(url: &str, file_name: &str) -> Result<()> {
+    let download_error = |error: &dyn fmt::Display| {
+        Error::Download(format!("could not download {}: {}", url, error))
+    };
     let client = reqwest::Client::new();
-    let mut response = client.get(url).send().await?;
-    let mut file = File::create(file_name)?;
+    let mut response = client
+        .get(url)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|error| download_error(&error))?;
+    let mut file = File::create(file_name).map_err(|error| download_error(&error))?;
     let mut downloaded: u64 = 0;
-    let length = response.content_length().unwrap_or(0);
-    let pb = ProgressBar::new(length);
+    let pb = match (output::json(), response.content_length()) {
+        (true, _) => ProgressBar::hidden(),
+        (false, Some(length)) => ProgressBar::new(length),
+        (false, None) => ProgressBar::new_spinner(),
+    };
     pb.set_style(ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({eta})")
         .unwrap()
         .with_key("eta", |state: &ProgressState, w: &mut dyn fmt::Write| write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap())
         .progress_chars("#>-"));
-    while let Some(chunk) = response.chunk().await? {
-        file.write_all(&chunk)?;
-        let new = min(downloaded + chunk.len() as u64, length);
-        downloaded = new;
-        pb.set_position(new);
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|error| download_error(&error))?
+    {
+        file.write_all(&chunk)
+            .map_err(|error| download_error(&error))?;
+        downloaded += chunk.len() as u64;
+        pb.set_position(downloaded);
     }
     pb.finish_with_message("download complete");
-    let tar_gz = File::open(file_name)?;
+    let archive_error = |error: std::io::Error| {
+        Error::Archive(format!("could not unpack {}: {}", file_name, error))
+    };
+    let tar_gz = File::open(file_name).map_err(archive_error)?;
     let tar = GzDecoder::new(tar_gz);
     let mut archive = Archive::new(tar);
-    archive.unpack(".")?;
+    archive.unpack(".").map_err(archive_error)?;
     remove_file(file_name)?;
+    output::push(
+        "downloaded",
+        serde_json::json!({"url": url, "bytes": downloaded}),
+    );
     Ok(())
 }
 
-fn prompt_sqlite_source(config: &mut Configuration) -> Result<()> {
-    let display_name: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Display name:")
-        .default("SQLite Source".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let origin: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Origin:")
-        .default("origin".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let path: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Database Path:")
-        .default("data.db".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let query: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Query:")
-        .default("select * from test".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    config.add_sqlite_connector_source(display_name, origin, path, query);
-    Ok(())
+/// The source or destination being edited, whose values become prompt defaults.
+struct Current<'a>(Option<&'a toml::Table>);
+
+/// Connection url split into the answers of the url prompts.
+struct UrlParts {
+    user: String,
+    password: Option<String>,
+    host: String,
+    port: String,
+    database: String,
+    params: Vec<(String, String)>,
 }
 
-fn prompt_sqlite_destination(config: &mut Configuration) -> Result<()> {
-    let display_name: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Display name:")
-        .default("SQLite Destination".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let path: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Database Path:")
-        .default("destination.db".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let truncate: bool = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Truncate:")
-        .default(false)
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    config.add_sqlite_connector_destination(display_name, path, truncate);
-    Ok(())
+impl UrlParts {
+    fn new(user: &str, host: &str, port: &str, database: &str) -> Self {
+        UrlParts {
+            user: user.to_string(),
+            password: None,
+            host: host.to_string(),
+            port: port.to_string(),
+            database: database.to_string(),
+            params: Vec::new(),
+        }
+    }
 }
 
-fn prompt_postgres_destination(config: &mut Configuration) -> Result<()> {
-    let display_name: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Display name:")
-        .default("Postgres destination".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let user: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Postgres username:")
-        .default("user".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let password = Password::with_theme(&ColorfulTheme::default())
-        .with_prompt("Postgres password:")
-        .interact()
-        .unwrap();
+impl Current<'_> {
+    fn get(&self, key: &str) -> Option<&toml::Value> {
+        self.0.and_then(|table| table.get(key))
+    }
 
-    let address: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Server address:")
-        .default("127.0.0.1".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let port: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Postgres port:")
-        .default("5432".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let database: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Database name:")
-        .default("db".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let schema: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Schema:")
-        .default("public".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let truncate: bool = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Truncate:")
-        .default(false)
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let postgres_url = format!(
-        "postgres://{}:{}@{}:{}/{}",
-        user, password, address, port, database
-    );
-    config.add_postgres_connector_destination(display_name, postgres_url, schema, truncate);
-    Ok(())
+    fn optional_string(&self, key: &str) -> Option<String> {
+        self.get(key)
+            .and_then(|value| value.as_str())
+            .map(str::to_string)
+    }
+
+    fn string(&self, key: &str, default: &str) -> String {
+        self.optional_string(key)
+            .unwrap_or_else(|| default.to_string())
+    }
+
+    fn boolean(&self, key: &str, default: bool) -> bool {
+        self.get(key)
+            .and_then(|value| value.as_bool())
+            .unwrap_or(default)
+    }
+
+    fn integer(&self, key: &str, default: i64) -> i64 {
+        self.get(key)
+            .and_then(|value| value.as_integer())
+            .unwrap_or(default)
+    }
+
+    // decomposes a stored url back into its parts, falling back to `defaults`
+    fn url_parts(&self, key: &str, defaults: UrlParts) -> UrlParts {
+        let url = match self
+            .optional_string(key)
+            .and_then(|url| url::Url::parse(&url).ok())
+        {
+            Some(url) => url,
+            None => return defaults,
+        };
+        let decode = |value: &str| percent_decode_str(value).decode_utf8_lossy().into_owned();
+        UrlParts {
+            user: match url.username() {
+                "" => defaults.user,
+                user => decode(user),
+            },
+            password: url.password().map(decode),
+            host: url.host_str().map(str::to_string).unwrap_or(defaults.host),
+            port: url
+                .port()
+                .map(|port| port.to_string())
+                .unwrap_or(defaults.port),
+            database: match url.path().trim_start_matches('/') {
+                "" => defaults.database,
+                database => decode(database),
+            },
+            params: url.query_pairs().into_owned().collect(),
+        }
+    }
 }
 
-fn prompt_kafka_destination(config: &mut Configuration) -> Result<()> {
-    let display_name: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Display name:")
-        .default("Kafka Destination".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let brokers: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Broker:")
-        .default("localhost:9092".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let topic: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Topic:")
-        .default("test".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    config.add_kafka_destination(display_name, brokers, topic);
-    Ok(())
+// secrets may be entered as `${env:NAME}` or `${file:PATH}` references,
+// when editing, an empty answer keeps the current password
+fn prompt_password(
+    p: &mut dyn Prompter,
+    prompt: &str,
+    flag: &str,
+    current: Option<String>,
+) -> Result<String> {
+    static HINT: std::sync::Once = std::sync::Once::new();
+    HINT.call_once(|| {
+        output::note(
+            "Secrets can be entered as `${env:NAME}`, `${file:PATH}` or `${vault:NAME}` to store a reference instead of the value."
+                .dimmed(),
+        );
+    });
+    let mut text = Text::new(prompt)
+        .validate(&secrets::check_references)
+        .flag(flag);
+    if current.is_some() {
+        text = Text::new(format!(
+            "{} (leave empty to keep current):",
+            prompt.trim_end_matches(':')
+        ))
+        .validate(&secrets::check_references)
+        .allow_empty(true)
+        .flag(flag);
+    }
+    let password = p.password(text)?;
+    if secrets::is_reference(&password) {
+        output::note(format!("storing reference `{}`", password));
+    }
+    Ok(match current {
+        Some(current) if password.is_empty() => current,
+        _ => password,
+    })
 }
 
-fn prompt_postgres_source(config: &mut Configuration) -> Result<()> {
-    let display_name: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Display name:")
-        .default("Postgres Source".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let user: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Postgres username:")
-        .default("user".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let password = Password::with_theme(&ColorfulTheme::default())
-        .with_prompt("Postgres password:")
-        .interact()
-        .unwrap();
-    let address: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Server address:")
-        .default("localhost".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let port: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Postgres port:")
-        .default("5432".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let database: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Database name:")
-        .default("test".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let origin: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Origin:")
-        .default("origin".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let query: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Query:")
-        .default("select * from test".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let poll_interval: i32 = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Poll interval (seconds):")
-        .default(5)
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let postgres_url = format!(
-        "postgres://{}:{}@{}:{}/{}",
-        user, password, address, port, database
-    );
-    config.add_postgres_connector_source(display_name, postgres_url, origin, query, poll_interval);
-    Ok(())
+// like `prompt_password`, but an empty answer means no secret
+fn prompt_optional_password(
+    p: &mut dyn Prompter,
+    prompt: &str,
+    flag: &str,
+    current: Option<String>,
+) -> Result<Option<String>> {
+    let password = p.password(
+        Text::new(prompt)
+            .validate(&secrets::check_references)
+            .allow_empty(true)
+            .flag(flag),
+    )?;
+    if secrets::is_reference(&password) {
+        output::note(format!("storing reference `{}`", password));
+    }
+    Ok(match password.is_empty() {
+        true => current,
+        false => Some(password),
+    })
 }
 
-fn prompt_excel_source(config: &mut Configuration) -> Result<()> {
-    let display_name: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Display name:")
-        .default("Excel Source".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let path: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Excel Path:")
-        .default("data.xlsx".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let sheets: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Sheets:")
-        .default("*".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let strict: bool = Confirm::with_theme(&ColorfulTheme::default())
-        .with_prompt("Strict:")
-        .default(false)
-        .interact()
-        .unwrap();
-    config.add_excel_connector_source(display_name, path, sheets, strict);
-    Ok(())
+// prompt without a default, unless there is a current value
+fn prompt_required(
+    p: &mut dyn Prompter,
+    prompt: &str,
+    flag: &str,
+    current: Option<String>,
+) -> Result<String> {
+    let mut text = Text::new(prompt).flag(flag);
+    if let Some(current) = current {
+        text = text.default(current);
+    }
+    p.input(text)
 }
-fn prompt_mysql_source(config: &mut Configuration) -> Result<()> {
-    let display_name: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Display name:")
-        .default("MySQL Source".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let user: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("MySQL username:")
-        .default("user".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let password = Password::with_theme(&ColorfulTheme::default())
-        .with_prompt("MySQL password:")
-        .interact()
-        .unwrap();
-    let address: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Server address:")
-        .default("localhost".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let port: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("MySQL port:")
-        .default("3306".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let database: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Database name:")
-        .default("test".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let origin: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Origin:")
-        .default("origin".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let query: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Query:")
-        .default("select * from test".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let poll_interval: i32 = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Poll interval (seconds):")
-        .default(5)
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let mysql_url = format!(
-        "mysql://{}:{}@{}:{}/{}",
-        user, password, address, port, database
-    );
-    config.add_mysql_connector_source(display_name, mysql_url, origin, query, poll_interval);
-    Ok(())
+
+// the `mycelial add` flag giving the value of `field`
+fn field_flag(field: &Field) -> String {
+    format!("--{}", field.flag())
 }
 
-fn prompt_mysql_destination(config: &mut Configuration) -> Result<()> {
-    let display_name: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Display name:")
-        .default("MySQL destination".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let user: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("MySQL username:")
-        .default("user".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let password = Password::with_theme(&ColorfulTheme::default())
-        .with_prompt("MySQL password:")
-        .interact()
-        .unwrap();
-    let address: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Server address:")
-        .default("127.0.0.1".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let port: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("MySQL port:")
-        .default("3306".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let database: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Database name:")
-        .default("db".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let truncate: bool = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Truncate:")
-        .default(false)
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
+// picks one of `items`, the current value or the first item is the default
+fn prompt_choice(
+    p: &mut dyn Prompter,
+    prompt: &str,
+    items: &[&str],
+    current: Option<String>,
+) -> Result<String> {
+    let default = current
+        .and_then(|current| items.iter().position(|item| *item == current))
+        .unwrap_or(0);
+    let index = p.select(prompt, items, Some(default))?;
+    Ok(items[index].to_string())
+}
 
-    let mysql_url = format!(
-        "mysql://{}:{}@{}:{}/{}",
-        user, password, address, port, database
-    );
-    config.add_mysql_connector_destination(display_name, mysql_url, truncate);
-    Ok(())
+fn prompt_host(p: &mut dyn Prompter, flag: &str, current: String) -> Result<String> {
+    p.input(
+        Text::new("Server address:")
+            .default(current)
+            .validate(&connection::check_host)
+            .flag(flag),
+    )
 }
-fn prompt_file_source(config: &mut Configuration) -> Result<()> {
-    let display_name: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Display name:")
-        .default("file source".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let path: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Path:")
-        .default("file.txt".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    config.add_file_source(display_name, path);
-    Ok(())
+
+fn prompt_port(p: &mut dyn Prompter, prompt: &str, flag: &str, current: String) -> Result<String> {
+    p.input(
+        Text::new(prompt)
+            .default(current)
+            .validate(&connection::check_port)
+            .flag(flag),
+    )
 }
 
-fn prompt_file_destination(config: &mut Configuration) -> Result<()> {
-    let display_name: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Display name:")
-        .default("file destination".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let path: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Path:")
-        .default("file.txt".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    config.add_file_destination(display_name, path);
-    Ok(())
+// removes a url query parameter, returning its value
+fn take_param(params: &mut Vec<(String, String)>, key: &str) -> String {
+    match params.iter().position(|(k, _)| k == key) {
+        Some(index) => params.remove(index).1,
+        None => String::new(),
+    }
 }
 
-fn prompt_snowflake_destination(config: &mut Configuration) -> Result<()> {
-    let display_name: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Display name:")
-        .default("Snowflake Destination".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let username: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Snowflake username:")
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let password = Password::with_theme(&ColorfulTheme::default())
-        .with_prompt("Snowflake password:")
-        .interact()
-        .unwrap();
-    let role: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Snowflake role:")
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let account_name: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Snowflake account name:")
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let organization_name: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Snowflake organization name:")
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let warehouse: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Snowflake warehouse:")
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let database: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Database name:")
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let schema: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Schema:")
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
-    let account_identifier = format!("{}-{}", organization_name, account_name);
-    let truncate: bool = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Truncate:")
-        .default(false)
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
+// TLS settings, taken out of `params` and returned as url query parameters
+fn prompt_tls(
+    p: &mut dyn Prompter,
+    tls: &TlsParams,
+    params: &mut Vec<(String, String)>,
+) -> Result<Vec<(String, String)>> {
+    let current_mode = take_param(params, tls.mode);
+    let current_ca = take_param(params, tls.ca);
+    let current_cert = take_param(params, tls.cert);
+    let current_key = take_param(params, tls.key);
+    let use_tls = p.confirm("Use TLS?", Some(!current_mode.is_empty()))?;
+    if !use_tls {
+        return Ok(Vec::new());
+    }
+    let default_mode = match tls.modes.iter().position(|mode| *mode == current_mode) {
+        Some(index) => index,
+        None => tls
+            .modes
+            .iter()
+            .position(|mode| *mode == tls.default_mode)
+            .unwrap(),
+    };
+    let mode = p.select("TLS mode:", tls.modes, Some(default_mode))?;
+    let check_file = |input: &str| connection::check_file(input.trim());
+    let mut prompt_file = |prompt: &str, flag: &str, current: String| -> Result<String> {
+        let text = Text::new(prompt)
+            .initial(current)
+            .allow_empty(true)
+            .validate(&check_file)
+            .flag(flag);
+        Ok(p.input(text)?.trim().to_string())
+    };
+    let ca = prompt_file("CA certificate path (optional):", "--ssl-ca", current_ca)?;
+    let cert = prompt_file(
+        "Client certificate path (optional):",
+        "--ssl-cert",
+        current_cert,
+    )?;
+    let key = prompt_file("Client key path (optional):", "--ssl-key", current_key)?;
+    Ok(vec![
+        (tls.mode.to_string(), tls.modes[mode].to_string()),
+        (tls.ca.to_string(), ca),
+        (tls.cert.to_string(), cert),
+        (tls.key.to_string(), key),
+    ])
+}
 
-    config.add_snowflake_connector_destination(
+// asks for the display name, which can't be one of `taken`, and every field
+// of `connector`; the values of the entry being edited are the defaults
+fn prompt_connector(
+    p: &mut dyn Prompter,
+    connector: &Connector,
+    taken: &[String],
+    current: Current,
+) -> Result<Section> {
+    let check_name = |input: &str| match taken.iter().any(|name| name == input) {
+        true => Err(format!(
+            "a {} named `{}` already exists",
+            connector.kind, input
+        )),
+        false => Ok(()),
+    };
+    let display_name = p.input(
+        Text::new("Display name:")
+            .default(current.string("display_name", &connector.display_name))
+            .validate(&check_name),
+    )?;
+    let mut answers = toml::Table::new();
+    for field in connector.fields.iter() {
+        if !connector.applies(field, &answers) {
+            continue;
+        }
+        if let Some(value) = prompt_field(p, connector, field, &answers, &current)? {
+            answers.insert(field.name.clone(), value);
+        }
+    }
+    let fields = connector
+        .stored_fields()
+        .filter_map(|field| {
+            answers
+                .remove(&field.name)
+                .map(|value| (field.name.clone(), value))
+        })
+        .collect();
+    Ok(Section {
+        section_type: connector.section_type.clone(),
         display_name,
-        username,
-        password,
-        role,
-        account_identifier,
-        warehouse,
-        database,
-        schema,
-        truncate,
+        fields,
+    })
+}
+
+// returns `None` for optional fields left empty or at their default,
+// `answers` holds the fields asked for so far
+fn prompt_field(
+    p: &mut dyn Prompter,
+    connector: &Connector,
+    field: &Field,
+    answers: &toml::Table,
+    current: &Current,
+) -> Result<Option<toml::Value>> {
+    let name = field.name.as_str();
+    let default = field.suggestion().unwrap_or_default();
+    let flag = field_flag(field);
+    let value = match field.field_type {
+        FieldType::Bool if !field.typed => {
+            let default = current.boolean(name, default == "true");
+            return Ok(Some(toml::Value::Boolean(
+                p.confirm(&field.prompt, Some(default))?,
+            )));
+        }
+        FieldType::Bool => {
+            let check_bool = |input: &str| {
+                input
+                    .parse::<bool>()
+                    .map(|_| ())
+                    .map_err(|_| "must be true or false".to_string())
+            };
+            let value = p.input(
+                Text::new(&field.prompt)
+                    .default(current.boolean(name, default == "true").to_string())
+                    .validate(&check_bool)
+                    .flag(&flag),
+            )?;
+            return Ok(Some(toml::Value::Boolean(value == "true")));
+        }
+        FieldType::Integer => {
+            let min = field.min.unwrap_or(i64::MIN);
+            let check_integer = |input: &str| match input.parse::<i64>() {
+                Ok(value) if value >= min => Ok(()),
+                Ok(_) => Err(format!("must be at least {}", min)),
+                Err(error) => Err(error.to_string()),
+            };
+            let value = p.input(
+                Text::new(&field.prompt)
+                    .default(
+                        current
+                            .integer(name, default.parse().unwrap_or(min.max(0)))
+                            .to_string(),
+                    )
+                    .validate(&check_integer)
+                    .flag(&flag),
+            )?;
+            return Ok(Some(toml::Value::Integer(value.parse()?)));
+        }
+        FieldType::Choice => {
+            let choices: Vec<&str> = field.choices.iter().map(String::as_str).collect();
+            let current = match (field.prompt_only, current.0) {
+                (true, Some(table)) => Some(connector.value_of(name, table)),
+                _ => current.optional_string(name),
+            };
+            prompt_choice(
+                p,
+                &field.prompt,
+                &choices,
+                current.or(field.default.clone()),
+            )?
+        }
+        FieldType::File => {
+            let check_file = |input: &str| match input.trim() {
+                "" if field.required => {
+                    Err(format!("{} must not be empty", name.replace('_', " ")))
+                }
+                input => connection::check_file(input),
+            };
+            p.input(
+                Text::new(&field.prompt)
+                    .initial(current.string(name, &default))
+                    .allow_empty(field.is_optional())
+                    .validate(&check_file)
+                    .flag(&flag),
+            )?
+            .trim()
+            .to_string()
+        }
+        FieldType::Brokers => p.input(
+            Text::new(&field.prompt)
+                .default(current.string(name, &default))
+                .validate(&connection::check_brokers)
+                .flag(&flag),
+        )?,
+        FieldType::Url => prompt_url(p, field, current)?,
+        FieldType::Account => prompt_account(p, field, current)?,
+        FieldType::String if field.introspect.is_some() => {
+            let introspect = field.introspect.as_ref().unwrap();
+            let path = answers
+                .get(&introspect.path)
+                .and_then(toml::Value::as_str)
+                .unwrap_or_default();
+            match introspect.introspect_type {
+                IntrospectType::Sqlite => prompt_query(p, field, path, current)?,
+                IntrospectType::Excel => prompt_sheets(p, field, path, current)?,
+            }
+        }
+        FieldType::String => match (field.secret, field.required) {
+            (true, true) => {
+                prompt_password(p, &field.prompt, &flag, current.optional_string(name))?
+            }
+            (true, false) => {
+                prompt_optional_password(p, &field.prompt, &flag, current.optional_string(name))?
+                    .unwrap_or_default()
+            }
+            (false, true) => prompt_required(
+                p,
+                &field.prompt,
+                &flag,
+                current.optional_string(name).or(field.suggestion()),
+            )?,
+            (false, false) => p
+                .input(
+                    Text::new(&field.prompt)
+                        .initial(current.string(name, ""))
+                        .allow_empty(true)
+                        .flag(&flag),
+                )?
+                .trim()
+                .to_string(),
+        },
+    };
+    if !field.required && (value.is_empty() || field.default.as_ref() == Some(&value)) {
+        return Ok(None);
+    }
+    Ok(Some(toml::Value::String(value)))
+}
+
+// offers the tables and columns of the database the query reads from, and
+// checks the query with `EXPLAIN`. Asks for free text when the database
+// doesn't exist yet or has no tables.
+fn prompt_query(
+    p: &mut dyn Prompter,
+    field: &Field,
+    path: &str,
+    current: &Current,
+) -> Result<String> {
+    const WRITE_QUERY: &str = "Write a query";
+    let current_query = current.optional_string(&field.name);
+    let tables = match introspect::sqlite_tables(path) {
+        Ok(Some(tables)) if !tables.is_empty() => tables,
+        Ok(_) => {
+            return prompt_required(
+                p,
+                &field.prompt,
+                &field_flag(field),
+                current_query.or(field.suggestion()),
+            )
+        }
+        Err(error) => {
+            output::note(error.yellow());
+            return prompt_required(
+                p,
+                &field.prompt,
+                &field_flag(field),
+                current_query.or(field.suggestion()),
+            );
+        }
+    };
+    let mut items: Vec<&str> = tables.iter().map(|table| table.name.as_str()).collect();
+    items.push(WRITE_QUERY);
+    // an existing query is kept unless another table is picked
+    let default = match current_query {
+        Some(_) => items.len() - 1,
+        None => 0,
+    };
+    let answer = p.select("Table:", &items, Some(default))?;
+    if let Some(table) = tables.get(answer) {
+        let columns: Vec<&str> = table.columns.iter().map(String::as_str).collect();
+        let selected = p.multi_select(
+            "Columns (space to select, enter to confirm):",
+            &columns,
+            &vec![true; columns.len()],
+        )?;
+        let selected: Vec<&str> = match selected.len() == columns.len() {
+            true => Vec::new(),
+            false => selected.into_iter().map(|index| columns[index]).collect(),
+        };
+        let query = introspect::select_query(&table.name, &selected);
+        match introspect::sqlite_explain(path, &query) {
+            Ok(()) => {
+                output::note(format!("{} {}", field.prompt, query));
+                return Ok(query);
+            }
+            Err(error) => output::note(format!("`{}` is invalid: {}", query, error).yellow()),
+        }
+    }
+    let explain = |input: &str| introspect::sqlite_explain(path, input);
+    let mut text = Text::new(&field.prompt)
+        .validate(&explain)
+        .flag(field_flag(field));
+    if let Some(query) = current_query.or(field.suggestion()) {
+        text = text.default(query);
+    }
+    p.input(text)
+}
+
+// offers the sheets of the workbook with their size and header, then shows
+// the first rows of the chosen ones and whether strict mode accepts them
+fn prompt_sheets(
+    p: &mut dyn Prompter,
+    field: &Field,
+    path: &str,
+    current: &Current,
+) -> Result<String> {
+    let current_sheets = current.optional_string(&field.name);
+    let sheets = match introspect::excel_sheets(path) {
+        Ok(Some(sheets)) if !sheets.is_empty() => sheets,
+        Ok(_) => {
+            return prompt_required(
+                p,
+                &field.prompt,
+                &field_flag(field),
+                current_sheets.or(field.suggestion()),
+            )
+        }
+        Err(error) => {
+            output::note(error.yellow());
+            return prompt_required(
+                p,
+                &field.prompt,
+                &field_flag(field),
+                current_sheets.or(field.suggestion()),
+            );
+        }
+    };
+    let items: Vec<String> = sheets
+        .iter()
+        .map(|sheet| {
+            format!(
+                "{} ({} rows: {})",
+                sheet.name,
+                sheet.rows,
+                sheet.header.join(", ")
+            )
+        })
+        .collect();
+    let chosen: Option<Vec<&str>> = match current_sheets.as_deref() {
+        None | Some("*") => None,
+        Some(names) => Some(names.split(',').map(str::trim).collect()),
+    };
+    let defaults: Vec<bool> = sheets
+        .iter()
+        .map(|sheet| match &chosen {
+            Some(chosen) => chosen.contains(&sheet.name.as_str()),
+            None => true,
+        })
+        .collect();
+    let items: Vec<&str> = items.iter().map(String::as_str).collect();
+    let prompt = format!(
+        "{} (space to select, enter to confirm):",
+        field.prompt.trim_end_matches(':')
     );
-    Ok(())
+    let selected = loop {
+        let selected = p.multi_select(&prompt, &items, &defaults)?;
+        if !selected.is_empty() {
+            break selected;
+        }
+        output::note("select at least one sheet".yellow());
+    };
+    for index in selected.iter() {
+        print_sheet_preview(&sheets[*index]);
+    }
+    Ok(match selected.len() == sheets.len() {
+        true => "*".to_string(),
+        false => selected
+            .iter()
+            .map(|index| sheets[*index].name.as_str())
+            .collect::<Vec<_>>()
+            .join(","),
+    })
+}
+
+fn print_sheet_preview(sheet: &introspect::Sheet) {
+    let rows: Vec<Vec<String>> = std::iter::once(sheet.header.clone())
+        .chain(sheet.preview.iter().cloned())
+        .collect();
+    output::note("");
+    output::note(format!("sheet `{}`", sheet.name).bold());
+    print_table(&rows);
+    match &sheet.mismatch {
+        Some(mismatch) => output::note(format!("strict mode fails on {}", mismatch).yellow()),
+        None => output::note("strict mode accepts every cell, each column holds one type".green()),
+    }
+}
+
+// asks for the parts of a connection url and puts them together
+fn prompt_url(p: &mut dyn Prompter, field: &Field, current: &Current) -> Result<String> {
+    let spec = field.url.as_ref().unwrap();
+    let mut url = current.url_parts(
+        &field.name,
+        UrlParts::new(&spec.user, &spec.host, &spec.port, &spec.database),
+    );
+    let flag = field_flag(field);
+    let user = p.input(
+        Text::new(format!("{} username:", spec.label))
+            .default(url.user)
+            .flag(&flag),
+    )?;
+    let password = prompt_password(p, &format!("{} password:", spec.label), &flag, url.password)?;
+    let address = prompt_host(p, &flag, url.host)?;
+    let port = prompt_port(p, &format!("{} port:", spec.label), &flag, url.port)?;
+    let database = p.input(
+        Text::new("Database name:")
+            .default(url.database)
+            .flag(&flag),
+    )?;
+    // optional parameters, taken out of `params` so they aren't repeated
+    let mut params = Vec::new();
+    for param in spec.params.iter() {
+        let integer = param.param_type == FieldType::Integer;
+        let check_param = |input: &str| match input.trim() {
+            input if !integer || input.is_empty() || input.parse::<u32>().is_ok() => Ok(()),
+            _ => Err(format!("{} must be a number", param.name.replace('_', " "))),
+        };
+        let value = p.input(
+            Text::new(&param.prompt)
+                .initial(take_param(&mut url.params, &param.name))
+                .allow_empty(true)
+                .validate(&check_param)
+                .flag(&flag),
+        )?;
+        params.push((param.name.clone(), value.trim().to_string()));
+    }
+    let scheme = &spec.schemes[0];
+    if let Some(tls) = connection::tls_params(scheme) {
+        params.extend(prompt_tls(p, tls, &mut url.params)?);
+    }
+    Ok(ConnectionUrl::new(scheme, &address, &port, &database)
+        .credentials(&user, &password)
+        .params(params)
+        .params(url.params)
+        .build()?)
+}
+
+// Snowflake account identifiers are stored as `<organization>-<account>`,
+// others, taken from an account url, are offered back as that url
+fn prompt_account(p: &mut dyn Prompter, field: &Field, current: &Current) -> Result<String> {
+    let (organization, account) = match current.optional_string(&field.name) {
+        Some(identifier) => match identifier.split_once('-') {
+            Some((organization, account)) if !identifier.contains('.') => {
+                (Some(organization.to_string()), Some(account.to_string()))
+            }
+            _ => (
+                None,
+                Some(format!("https://{}.snowflakecomputing.com", identifier)),
+            ),
+        },
+        None => (None, None),
+    };
+    let check_account = |input: &str| connection::snowflake_account_identifier(input).map(|_| ());
+    let flag = field_flag(field);
+    let mut text = Text::new(&field.prompt)
+        .validate(&check_account)
+        .flag(&flag);
+    if let Some(account) = account {
+        text = text.default(account);
+    }
+    let account_name = p.input(text)?;
+    // a url already names the organization
+    let is_url = account_name.contains("://")
+        || account_name
+            .trim_end_matches('/')
+            .ends_with(".snowflakecomputing.com");
+    match is_url {
+        true => Ok(connection::snowflake_account_identifier(&account_name)?),
+        false => {
+            let organization_name =
+                prompt_required(p, "Snowflake organization name:", &flag, organization)?;
+            Ok(format!("{}-{}", organization_name, account_name.trim()))
+        }
+    }
 }
 
 pub enum ConfigAction {
@@ -815,7 +1404,10 @@ pub enum ConfigAction {
     UseExisting,
 }
 
-fn config_file_action(config_file_name: String) -> Result<(ConfigAction, std::string::String)> {
+fn config_file_action(
+    p: &mut dyn Prompter,
+    config_file_name: String,
+) -> Result<(ConfigAction, std::string::String)> {
     let config_path = Path::new(&config_file_name);
     const OVERWRITE: &str = "Overwrite file";
     const APPEND: &str = "Append to file";
@@ -824,71 +1416,65 @@ fn config_file_action(config_file_name: String) -> Result<(ConfigAction, std::st
     if !config_path.exists() {
         Ok((ConfigAction::Create, config_file_name))
     } else {
-        let answer = FuzzySelect::with_theme(&ColorfulTheme::default())
-            .with_prompt(&format!(
+        let answer = p.select(
+            &format!(
                 "The config file `{}` already exists, what would you like to do?",
                 config_file_name
-            ))
-            .items(&options)
-            .interact()
-            .unwrap();
-        match answer {
-            // OVERWRITE
-            0 => Ok((ConfigAction::Create, config_file_name)),
-            // APPEND
-            1 => Ok((ConfigAction::Append, config_file_name)),
+            ),
+            &options,
+            None,
+        )?;
+        match options[answer] {
+            OVERWRITE => Ok((ConfigAction::Create, config_file_name)),
+            APPEND => Ok((ConfigAction::Append, config_file_name)),
             // RENAME
-            2 => {
-                let new_config_file_name: String = Input::with_theme(&ColorfulTheme::default())
-                    .with_prompt("New config file name:")
-                    .default("config.toml".to_string())
-                    .allow_empty(false)
-                    .interact_text()
-                    .unwrap();
-                let result = config_file_action(new_config_file_name)?;
-                Ok(result)
-            }
             _ => {
-                panic!("Unknown config file action");
+                let new_config_file_name =
+                    p.input(Text::new("New config file name:").default("config.toml"))?;
+                let result = config_file_action(p, new_config_file_name)?;
+                Ok(result)
             }
         }
     }
 }
 
+/// Asks for the node, server, sources, destinations and pipelines of a new
+/// config, or what to do with an existing one, and saves it.
 pub async fn create_config(
+    p: &mut dyn Prompter,
     config_file_name: String,
     database_storage_path: Option<String>,
     config_action: Option<ConfigAction>,
     endpoint: Option<String>,
     token: Option<String>,
 ) -> Result<()> {
-    let (action, config_file_name) = if config_action.is_none() {
-        config_file_action(config_file_name)?
-    } else {
-        (config_action.unwrap(), config_file_name)
+    let (action, config_file_name) = match config_action {
+        Some(config_action) => (config_action, config_file_name),
+        None => config_file_action(p, config_file_name)?,
     };
     match action {
         ConfigAction::Create => {
-            do_create_config(config_file_name, database_storage_path, endpoint, token).await
+            do_create_config(p, config_file_name, database_storage_path, endpoint, token).await
         }
-        ConfigAction::Append => do_append_config(config_file_name).await,
+        ConfigAction::Append => do_append_config(p, config_file_name).await,
         ConfigAction::UseExisting => Ok(()),
     }
 }
 
-async fn do_append_config(config_file_name: String) -> Result<()> {
+async fn do_append_config(p: &mut dyn Prompter, config_file_name: String) -> Result<()> {
     match Configuration::load(&config_file_name) {
         Ok(mut config) => {
-            source_destination_loop(&mut config, config_file_name)?;
+            source_destination_loop(p, &mut config, config_file_name)?;
         }
         Err(error) => {
-            panic!("error loading config file: {}", error);
+            return Err(config_load_error(&config_file_name, error));
         }
     }
     Ok(())
 }
 
 async fn do_create_config(
+    p: &mut dyn Prompter,
     config_file_name: String,
     database_storage_path: Option<String>,
     endpoint: Option<String>,
@@ -899,19 +1485,9 @@ async fn do_create_config(
         None => "daemon.db".to_string(),
     };
     let mut config = Configuration::new();
-    let client_name: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Daemon Name:")
-        .default("My Daemon".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
+    let client_name = p.input(Text::new("Daemon Name:").default("My Daemon"))?;
 
-    let client_id: String = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("Daemon ID:")
-        .default("daemon".to_string())
-        .allow_empty(false)
-        .interact_text()
-        .unwrap();
+    let client_id = p.input(Text::new("Daemon ID:").default("daemon"))?;
 
     let id = Uuid::new_v4().to_string();
 
@@ -919,350 +1495,693 @@ async fn do_create_config(
 
     let control_plane = match endpoint {
         Some(endpoint) => endpoint,
-        None => {
-            let control_plane: String = Input::with_theme(&ColorfulTheme::default())
-                .with_prompt("Control Plane:")
-                .default("http://localhost:7777".to_string())
-                .allow_empty(false)
-                .interact_text()
-                .unwrap();
-            control_plane
-        }
+        None => p.input(
+            Text::new("Control Plane:")
+                .default("http://localhost:7777")
+                .flag("--endpoint"),
+        )?,
     };
     let auth_token = match token {
-        Some(token) => token,
-        None => {
-            let token = Password::with_theme(&ColorfulTheme::default())
-                .with_prompt("Auth Token:")
-                .interact()
-                .unwrap();
+        Some(token) => {
+            if let Err(error) = secrets::check_references(&token) {
+                return Err(Error::Usage(format!("--token: {}", error)).into());
+            }
             token
         }
+        None => prompt_password(p, "Auth Token:", "--token", None)?,
     };
 
     config.set_node(client_name, unique_id, database_storage_path, auth_token);
     config.set_server(control_plane);
 
-    source_destination_loop(&mut config, config_file_name)?;
+    source_destination_loop(p, &mut config, config_file_name)?;
     Ok(())
 }
 
-fn source_prompts(config: &mut Configuration, config_file_name: Option<String>) -> Result<()> {
-    const SQLITE_SOURCE: &str = "SQLite source";
-    const EXCEL_SOURCE: &str = "Excel source";
-    const POSTGRES_SOURCE: &str = "Postgres source";
-    const MYSQL_SOURCE: &str = "MySQL source";
-    const FILE_SOURCE: &str = "File source";
+// offers the connectors of `kind`, when `config_file_name` is set keeps
+// asking until the user picks "Exit" and saves the config. With `check` the
+// connection is tested before a connector is added.
+fn connector_prompts(
+    p: &mut dyn Prompter,
+    kind: Kind,
+    config: &mut Configuration,
+    config_file_name: Option<String>,
+    check: bool,
+    save_anyway: bool,
+) -> Result<()> {
     const EXIT: &str = "Exit";
     const CANCEL: &str = "Cancel";
-    const PROMPT: &str = "What type of source would you like to add?";
-    match config_file_name {
-        Some(config_file_name) => {
-            let options = vec![
-                SQLITE_SOURCE,
-                EXCEL_SOURCE,
-                POSTGRES_SOURCE,
-                MYSQL_SOURCE,
-                FILE_SOURCE,
-                EXIT,
-            ];
-            let source = FuzzySelect::with_theme(&ColorfulTheme::default())
-                .with_prompt(PROMPT)
-                .items(&options)
-                .interact()
-                .unwrap();
-            match source {
-                // SQLITE_SOURCE
-                0 => {
-                    prompt_sqlite_source(config)?;
-                }
-                // EXCEL_SOURCE
-                1 => {
-                    prompt_excel_source(config)?;
-                }
-                // POSTGRES_SOURCE
-                2 => {
-                    prompt_postgres_source(config)?;
-                }
-                // MYSQL_SOURCE
-                3 => {
-                    prompt_mysql_source(config)?;
-                }
-                // FILE_SOURCE
-                4 => {
-                    prompt_file_source(config)?;
-                }
-                // EXIT
-                5 => {
-                    match config.save(&config_file_name) {
-                        Ok(_) => {
-                            println!("{}", format!("{} saved!", config_file_name).green());
-                        }
-                        Err(_error) => {
-                            return Err(format!(
-                                "error creating config file `{}`",
-                                config_file_name
-                            )
-                            .into());
-                        }
-                    }
-                    return Ok(());
-                }
-                _ => {
-                    panic!("Unknown source type");
-                }
+    let connectors: Vec<&Connector> = connector::registry().visible(kind).collect();
+    let mut options: Vec<&str> = connectors
+        .iter()
+        .map(|connector| connector.label.as_str())
+        .collect();
+    options.push(match config_file_name {
+        Some(_) => EXIT,
+        None => CANCEL,
+    });
+    let answer = p.select(
+        &format!("What type of {} would you like to add?", kind),
+        &options,
+        None,
+    )?;
+    match (connectors.get(answer), config_file_name) {
+        (Some(connector), config_file_name) => {
+            let section = prompt_connector(p, connector, &config.names_of(kind), Current(None))?;
+            let passed = !check
+                || report_check(
+                    &section.display_name,
+                    check::run(p, connector, &section.fields),
+                );
+            if passed || save_anyway || p.confirm("Save anyway?", Some(false))? {
+                config.add_section(kind, section);
             }
-            source_prompts(config, Some(config_file_name))?;
-        }
-        None => {
-            let options = vec![
-                SQLITE_SOURCE,
-                EXCEL_SOURCE,
-                POSTGRES_SOURCE,
-                MYSQL_SOURCE,
-                FILE_SOURCE,
-                CANCEL,
-            ];
-            let source = FuzzySelect::with_theme(&ColorfulTheme::default())
-                .with_prompt(PROMPT)
-                .items(&options)
-                .interact()
-                .unwrap();
-            match source {
-                // SQLITE_SOURCE
-                0 => {
-                    prompt_sqlite_source(config)?;
-                }
-                // EXCEL_SOURCE
-                1 => {
-                    prompt_excel_source(config)?;
-                }
-                // POSTGRES_SOURCE
-                2 => {
-                    prompt_postgres_source(config)?;
-                }
-                // MYSQL_SOURCE
-                3 => {
-                    prompt_mysql_source(config)?;
-                }
-                // FILE_SOURCE
-                4 => {
-                    prompt_file_source(config)?;
-                }
-                // CANCEL
-                5 => {
-                    return Ok(());
-                }
-                _ => {
-                    panic!("Unknown source type");
-                }
+            if let Some(config_file_name) = config_file_name {
+                connector_prompts(p, kind, config, Some(config_file_name), check, save_anyway)?;
             }
         }
-    }
-    Ok(())
-}
-
-fn destination_prompts(config: &mut Configuration, config_file_name: Option<String>) -> Result<()> {
-    const SQLITE_DESTINATION: &str = "SQLite destination";
-    const POSTGRES_DESTINATION: &str = "Postgres destination";
-    const MYSQL_DESTINATION: &str = "MySQL destination";
-    const KAFKA_DESTINATION: &str = "Kafka destination";
-    const SNOWFLAKE_DESTINATION: &str = "Snowflake destination";
-    const FILE_DESTINATION: &str = "File destination";
-    const EXIT: &str = "Exit";
-    const CANCEL: &str = "Cancel";
-    const PROMPT: &str = "What type of destination would you like to add?";
-    match config_file_name {
-        Some(config_file_name) => {
-            let options = vec![
-                SQLITE_DESTINATION,
-                POSTGRES_DESTINATION,
-                MYSQL_DESTINATION,
-                KAFKA_DESTINATION,
-                SNOWFLAKE_DESTINATION,
-                FILE_DESTINATION,
-                EXIT,
-            ];
-            let destination = FuzzySelect::with_theme(&ColorfulTheme::default())
-                .with_prompt(PROMPT)
-                .items(&options)
-                .interact()
-                .unwrap();
-            match destination {
-                // SQLITE_DESTINATION
-                0 => {
-                    prompt_sqlite_destination(config)?;
-                }
-                // POSTGRES_DESTINATION
-                1 => {
-                    prompt_postgres_destination(config)?;
-                }
-                // MYSQL_DESTINATION
-                2 => {
-                    prompt_mysql_destination(config)?;
-                }
-                // KAFKA_DESTINATION
-                3 => {
-                    prompt_kafka_destination(config)?;
-                }
-                // SNOWFLAKE_DESTINATION
-                4 => {
-                    prompt_snowflake_destination(config)?;
-                }
-                // FILE_DESTINATION
-                5 => {
-                    prompt_file_destination(config)?;
-                }
-                // EXIT
-                6 => {
-                    match config.save(&config_file_name) {
-                        Ok(_) => {
-                            println!("{}", "config file saved!".green());
-                        }
-                        Err(_error) => {
-                            return Err(format!(
-                                "error creating config file `{}`",
-                                config_file_name
-                            )
-                            .into());
-                        }
-                    }
-                    return Ok(());
-                }
-                _ => {
-                    panic!("Unknown destination type");
-                }
+        // EXIT
+        (None, Some(config_file_name)) => match config.save(&config_file_name) {
+            Ok(_) => {
+                output::say(format!("{} saved!", config_file_name).green());
+                report_config(&config_file_name, config);
             }
-            destination_prompts(config, Some(config_file_name))?;
-        }
-        None => {
-            let options = vec![
-                SQLITE_DESTINATION,
-                POSTGRES_DESTINATION,
-                MYSQL_DESTINATION,
-                KAFKA_DESTINATION,
-                SNOWFLAKE_DESTINATION,
-                FILE_DESTINATION,
-                CANCEL,
-            ];
-            let destination = FuzzySelect::with_theme(&ColorfulTheme::default())
-                .with_prompt(PROMPT)
-                .items(&options)
-                .interact()
-                .unwrap();
-            match destination {
-                // SQLITE_DESTINATION
-                0 => {
-                    prompt_sqlite_destination(config)?;
-                }
-                // POSTGRES_DESTINATION
-                1 => {
-                    prompt_postgres_destination(config)?;
-                }
-                // MYSQL_DESTINATION
-                2 => {
-                    prompt_mysql_destination(config)?;
-                }
-                // KAFKA_DESTINATION
-                3 => {
-                    prompt_kafka_destination(config)?;
-                }
-                // SNOWFLAKE_DESTINATION
-                4 => {
-                    prompt_snowflake_destination(config)?;
-                }
-                // FILE_DESTINATION
-                5 => {
-                    prompt_file_destination(config)?;
-                }
-                // CANCEL
-                6 => {
-                    return Ok(());
-                }
-
-                _ => {
-                    panic!("Unknown destination type");
-                }
+            Err(error) => {
+                return Err(Error::Save(format!(
+                    "error creating config file `{}`: {}",
+                    config_file_name, error
+                ))
+                .into());
             }
-        }
+        },
+        // CANCEL
+        (None, None) => {}
     }
     Ok(())
 }
 
-pub async fn add_source(config_file_name: &str) -> Result<()> {
+pub async fn add_source(
+    p: &mut dyn Prompter,
+    config_file_name: &str,
+    check: bool,
+    save_anyway: bool,
+) -> Result<()> {
+    ensure(
+        "`mycelial add --source`",
+        "pass the source type instead, see `mycelial add source --help`",
+    )?;
+    // before the config is loaded, so it is read with the daemon's connectors
+    connector::installed();
     let config_file_name_path = Path::new(config_file_name);
     if config_file_name_path.exists() {
         match Configuration::load(config_file_name) {
             Ok(mut config) => {
-                source_prompts(&mut config, Some(config_file_name.to_string()))?;
+                connector_prompts(
+                    p,
+                    Kind::Source,
+                    &mut config,
+                    Some(config_file_name.to_string()),
+                    check,
+                    save_anyway,
+                )?;
             }
-            Err(_error) => {
-                panic!("error loading config file");
+            Err(error) => {
+                return Err(config_load_error(config_file_name, error));
             }
         }
     } else {
-        create_config(config_file_name.to_string(), None, None, None, None).await?;
+        create_config(p, config_file_name.to_string(), None, None, None, None).await?;
     }
     Ok(())
 }
 
-pub async fn add_destination(config_file_name: &str) -> Result<()> {
+pub async fn add_destination(
+    p: &mut dyn Prompter,
+    config_file_name: &str,
+    check: bool,
+    save_anyway: bool,
+) -> Result<()> {
+    ensure(
+        "`mycelial add --destination`",
+        "pass the destination type instead, see `mycelial add destination --help`",
+    )?;
+    // before the config is loaded, so it is read with the daemon's connectors
+    connector::installed();
     let config_file_name_path = Path::new(config_file_name);
     if config_file_name_path.exists() {
         match Configuration::load(config_file_name) {
             Ok(mut config) => {
-                destination_prompts(&mut config, Some(config_file_name.to_string()))?;
+                connector_prompts(
+                    p,
+                    Kind::Destination,
+                    &mut config,
+                    Some(config_file_name.to_string()),
+                    check,
+                    save_anyway,
+                )?;
             }
-            Err(_error) => {
-                panic!("error loading config file");
+            Err(error) => {
+                return Err(config_load_error(config_file_name, error));
             }
         }
     } else {
-        create_config(config_file_name.to_string(), None, None, None, None).await?;
+        create_config(p, config_file_name.to_string(), None, None, None, None).await?;
     }
     Ok(())
 }
 
-fn source_destination_loop(config: &mut Configuration, config_file_name: String) -> Result<()> {
+fn source_destination_loop(
+    p: &mut dyn Prompter,
+    config: &mut Configuration,
+    config_file_name: String,
+) -> Result<()> {
     loop {
         const ADD_SOURCE: &str = "Add Source";
         const ADD_DESTINATION: &str = "Add Destination";
+        const ADD_PIPELINE: &str = "Add Pipeline";
         const EXIT: &str = "Exit";
         const PROMPT: &str = "What would you like to do?";
-        let options = vec![ADD_SOURCE, ADD_DESTINATION, EXIT];
-        let answer = FuzzySelect::with_theme(&ColorfulTheme::default())
-            .with_prompt(PROMPT)
-            .items(&options)
-            .interact()
-            .unwrap();
+        let options = vec![ADD_SOURCE, ADD_DESTINATION, ADD_PIPELINE, EXIT];
+        let answer = p.select(PROMPT, &options, None)?;
         // EXIT
-        if answer == 2 {
+        if answer == 3 {
             match config.save(&config_file_name) {
                 Ok(_) => {
-                    println!("{}", format!("{} saved!", config_file_name).green());
+                    output::say(format!("{} saved!", config_file_name).green());
+                    report_config(&config_file_name, config);
                 }
-                Err(_error) => {
-                    return Err("error creating config file".into());
+                Err(error) => {
+                    return Err(Error::Save(format!(
+                        "error creating config file `{}`: {}",
+                        config_file_name, error
+                    ))
+                    .into());
                 }
             }
             break;
         } else if answer == 0
         /* ADD_SOURCE */
         {
-            source_prompts(config, None)?;
+            connector_prompts(p, Kind::Source, config, None, false, false)?;
         } else if answer == 1
         /* ADD_DESTINATION */
         {
-            destination_prompts(config, None)?;
+            connector_prompts(p, Kind::Destination, config, None, false, false)?;
+        } else if answer == 2
+        /* ADD_PIPELINE */
+        {
+            prompt_pipeline(p, config)?;
         }
     }
     Ok(())
 }
 
-fn can_start_client(config_file_name: &str) -> bool {
-    let myceliald_path = Path::new("myceliald");
-    let config_path = Path::new(config_file_name);
-    myceliald_path.exists() && config_path.exists()
+fn prompt_pipeline(p: &mut dyn Prompter, config: &mut Configuration) -> Result<()> {
+    let sources = config.source_names();
+    let destinations = config.destination_names();
+    if sources.is_empty() || destinations.is_empty() {
+        output::note("add a source and a destination before adding a pipeline".yellow());
+        return Ok(());
+    }
+    let source_items: Vec<&str> = sources.iter().map(String::as_str).collect();
+    let destination_items: Vec<&str> = destinations.iter().map(String::as_str).collect();
+    let source = p.select("Source:", &source_items, Some(0))?;
+    let selected = loop {
+        let selected = p.multi_select(
+            "Destinations (space to select, enter to confirm):",
+            &destination_items,
+            &[],
+        )?;
+        if !selected.is_empty() {
+            break selected;
+        }
+        output::note("select at least one destination".yellow());
+    };
+    let existing: Vec<String> = config
+        .pipelines()
+        .iter()
+        .map(|pipeline| pipeline.name.clone())
+        .collect();
+    let check_name = |input: &str| match existing.iter().any(|name| name == input) {
+        true => Err(format!("a pipeline named `{}` already exists", input)),
+        false => Ok(()),
+    };
+    let name = p.input(
+        Text::new("Pipeline name:")
+            .default(format!("{} pipeline", sources[source]))
+            .validate(&check_name),
+    )?;
+    let enabled = p.confirm("Enabled?", Some(true))?;
+    config.add_pipeline(Pipeline {
+        name,
+        source: sources[source].clone(),
+        destinations: selected
+            .into_iter()
+            .map(|index| destinations[index].clone())
+            .collect(),
+        enabled: Some(enabled),
+    });
+    Ok(())
+}
+
+fn config_load_error(
+    config_file_name: &str,
+    error: Box<dyn std::error::Error>,
+) -> Box<dyn std::error::Error + Send + Sync> {
+    Error::Config(format!(
+        "error loading config file `{}`: {}\nrun `mycelial config validate --config {}` for details",
+        config_file_name, error, config_file_name
+    ))
+    .into()
+}
+
+pub fn validate_config(config_file_name: &str) -> Result<()> {
+    connector::installed();
+    let contents = match read_to_string(config_file_name) {
+        Ok(contents) => contents,
+        Err(error) => {
+            return Err(Error::Config(format!(
+                "could not read config file `{}`: {}",
+                config_file_name, error
+            ))
+            .into())
+        }
+    };
+    let diagnostics = validate::validate(&contents);
+    output::set("config", config_file_name);
+    output::set("problems", &diagnostics);
+    if diagnostics.is_empty() {
+        output::say(format!("{} is valid", config_file_name).green());
+        return Ok(());
+    }
+    for diagnostic in diagnostics.iter() {
+        output::say(format!("{}:{}", config_file_name, diagnostic));
+    }
+    Err(Error::Config(format!(
+        "{} problem(s) found in `{}`",
+        diagnostics.len(),
+        config_file_name
+    ))
+    .into())
+}
+
+/// Writes a copy of the config with secret references resolved, readable only
+/// by the current user, creating its directory if needed. A config without
+/// references is copied as is. Returns whether there were references.
+pub fn resolve_secrets(
+    p: &mut dyn Prompter,
+    config_file_name: &str,
+    resolved_file_name: &str,
+    key_file: Option<&str>,
+) -> Result<bool> {
+    let resolved = secrets::resolve_config(p, config_file_name, key_file).map_err(|error| {
+        format!(
+            "error resolving secrets in `{}`: {}",
+            config_file_name, error
+        )
+    })?;
+    let path = Path::new(resolved_file_name);
+    if let Some(dir) = path.parent() {
+        secrets::create_private_dir(dir)?;
+    }
+    match resolved {
+        Some(resolved) => {
+            secrets::write_private(path, &resolved)?;
+            Ok(true)
+        }
+        None => {
+            secrets::write_private(path, &read_to_string(config_file_name)?)?;
+            Ok(false)
+        }
+    }
+}
+
+// prints rows in aligned columns, the first row in bold
+fn print_table(rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows.iter() {
+        for (column, cell) in row.iter().enumerate() {
+            match widths.get_mut(column) {
+                Some(width) => *width = (*width).max(cell.chars().count()),
+                None => widths.push(cell.chars().count()),
+            }
+        }
+    }
+    for (index, row) in rows.iter().enumerate() {
+        let line = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string();
+        match index {
+            0 => output::note(line.bold()),
+            _ => output::note(line),
+        }
+    }
+}
+
+// what a saved config holds, for the JSON output
+fn report_config(config_file_name: &str, config: &Configuration) {
+    output::set("config", config_file_name);
+    output::set("sources", config.source_names());
+    output::set("destinations", config.destination_names());
+    output::set(
+        "pipelines",
+        config
+            .pipelines()
+            .iter()
+            .map(|pipeline| pipeline.name.as_str())
+            .collect::<Vec<_>>(),
+    );
+}
+
+fn load_config(config_file_name: &str) -> Result<Configuration> {
+    Configuration::load(config_file_name)
+        .map_err(|error| config_load_error(config_file_name, error))
+}
+
+pub fn list_config(config_file_name: &str) -> Result<()> {
+    let config = load_config(config_file_name)?;
+    let entries = config.entries();
+    if output::json() {
+        let entries: Vec<serde_json::Value> = entries
+            .into_iter()
+            .map(|entry| {
+                serde_json::json!({
+                    "index": entry.index,
+                    "kind": entry.kind.to_string(),
+                    "type": entry.section_type,
+                    "display_name": entry.display_name,
+                    "target": entry.target,
+                })
+            })
+            .collect();
+        output::set("entries", entries);
+        output::set("pipelines", config.pipelines());
+        return Ok(());
+    }
+    if entries.is_empty() {
+        output::say("no sources or destinations configured".yellow());
+        return Ok(());
+    }
+    let header = ["INDEX", "KIND", "TYPE", "DISPLAY NAME", "TARGET"];
+    let rows: Vec<Vec<String>> = std::iter::once(header.map(str::to_string).to_vec())
+        .chain(entries.into_iter().map(|entry| {
+            vec![
+                entry.index.to_string(),
+                entry.kind.to_string(),
+                entry.section_type.to_string(),
+                entry.display_name,
+                entry.target,
+            ]
+        }))
+        .collect();
+    print_table(&rows);
+    if !config.pipelines().is_empty() {
+        output::say("");
+        output::say("PIPELINES".bold());
+    }
+    for pipeline in config.pipelines() {
+        output::say(format!(
+            "{}: {} -> {}{}",
+            pipeline.name,
+            pipeline.source,
+            pipeline.destinations.join(", "),
+            if pipeline.is_enabled() {
+                ""
+            } else {
+                " (disabled)"
+            }
+        ));
+    }
+    Ok(())
+}
+
+pub fn show_config_entry(config_file_name: &str, name: &str) -> Result<()> {
+    let config = load_config(config_file_name)?;
+    let entry = match config.find_entry(name) {
+        Some(entry) => entry,
+        None => return Err(no_such_entry(config_file_name, name)),
+    };
+    let contents = match config.entry_to_string(entry.index) {
+        Ok(contents) => contents,
+        Err(error) => return Err(format!("error rendering `{}`: {}", name, error).into()),
+    };
+    if output::json() {
+        let table: toml::Table = toml::from_str(&contents)?;
+        output::set("kind", entry.kind.to_string());
+        output::set("index", entry.index);
+        output::set("entry", table);
+        return Ok(());
+    }
+    output::say(format!("# {} {}", entry.kind, entry.index));
+    output::say(format!("[[{}s]]", entry.kind));
+    output::say(contents.trim_end());
+    Ok(())
+}
+
+// prints the outcome of a connectivity check, false if it failed
+fn report_check(display_name: &str, outcome: check::Outcome) -> bool {
+    let (result, detail, passed) = match outcome {
+        check::Outcome::Passed(detail) => {
+            output::say(format!("{}: ok, {}", display_name, detail).green());
+            ("ok", detail, true)
+        }
+        check::Outcome::Skipped(reason) => {
+            output::say(format!("{}: skipped, {}", display_name, reason).yellow());
+            ("skipped", reason, true)
+        }
+        check::Outcome::Failed(error) => {
+            output::say(format!("{}: failed, {}", display_name, error).red());
+            ("failed", error, false)
+        }
+    };
+    output::push(
+        "checks",
+        serde_json::json!({"display_name": display_name, "result": result, "detail": detail}),
+    );
+    passed
+}
+
+/// Tests the connection of one source or destination, or of all of them.
+pub fn test_config(p: &mut dyn Prompter, config_file_name: &str, name: Option<&str>) -> Result<()> {
+    let config = load_config(config_file_name)?;
+    let entries = match name {
+        Some(name) => match config.find_entry(name) {
+            Some(entry) => vec![entry],
+            None => return Err(no_such_entry(config_file_name, name)),
+        },
+        None => config.entries(),
+    };
+    if entries.is_empty() {
+        output::warn("no sources or destinations configured");
+        return Ok(());
+    }
+    let mut failed = 0;
+    for entry in entries.iter() {
+        let connector = connector::registry()
+            .find(entry.kind, &entry.section_type)
+            .unwrap();
+        let table = config.entry_table(entry.index).unwrap_or_default();
+        if !report_check(&entry.display_name, check::run(p, connector, &table)) {
+            failed += 1;
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        _ => Err(
+            Error::Connection(format!("{} of {} check(s) failed", failed, entries.len())).into(),
+        ),
+    }
+}
+
+/// Prints up to `limit` rows a source reads as a table, or records them for
+/// the JSON output.
+pub fn preview_source(
+    p: &mut dyn Prompter,
+    config_file_name: &str,
+    name: &str,
+    limit: usize,
+    json_lines: bool,
+) -> Result<()> {
+    let config = load_config(config_file_name)?;
+    let entry = match config.find_entry(name) {
+        Some(entry) => entry,
+        None => return Err(no_such_entry(config_file_name, name)),
+    };
+    if entry.kind != Kind::Source {
+        return Err(Error::Usage(format!(
+            "`{}` is a destination, only sources can be previewed",
+            name
+        ))
+        .into());
+    }
+    let connector = connector::registry()
+        .find(entry.kind, &entry.section_type)
+        .unwrap();
+    let table = config.entry_table(entry.index).unwrap_or_default();
+    let samples = preview::run(p, connector, &table, limit).map_err(|error| {
+        Error::Connection(format!(
+            "error previewing `{}`: {}",
+            entry.display_name, error
+        ))
+    })?;
+    output::set("source", &entry.display_name);
+    for sample in samples {
+        if output::json() || json_lines {
+            for row in sample.rows {
+                let mut object: serde_json::Map<String, serde_json::Value> =
+                    sample.columns.iter().cloned().zip(row).collect();
+                if let Some(title) = &sample.title {
+                    object.insert("sheet".to_string(), title.clone().into());
+                }
+                match json_lines {
+                    true => output::say(serde_json::Value::Object(object)),
+                    false => output::push("rows", object),
+                }
+            }
+            continue;
+        }
+        if let Some(title) = &sample.title {
+            output::say(format!("sheet `{}`", title).bold());
+        }
+        let count = sample.rows.len();
+        let rows: Vec<Vec<String>> = std::iter::once(sample.columns)
+            .chain(sample.rows.into_iter().map(|row| {
+                row.into_iter()
+                    .map(|value| match value {
+                        serde_json::Value::String(value) => value,
+                        serde_json::Value::Null => "NULL".to_string(),
+                        value => value.to_string(),
+                    })
+                    .collect()
+            }))
+            .collect();
+        print_table(&rows);
+        output::say(format!("({} row(s))", count));
+    }
+    Ok(())
+}
+
+pub fn remove_config_entry(config_file_name: &str, name: &str) -> Result<()> {
+    let mut config = load_config(config_file_name)?;
+    let entry = match config.find_entry(name) {
+        Some(entry) => config.remove_entry(entry.index).unwrap(),
+        None => return Err(no_such_entry(config_file_name, name)),
+    };
+    if let Err(error) = config.save(config_file_name) {
+        return Err(Error::Save(format!(
+            "error saving config file `{}`: {}",
+            config_file_name, error
+        ))
+        .into());
+    }
+    output::say(
+        format!(
+            "removed {} `{}` from {}",
+            entry.kind, entry.display_name, config_file_name
+        )
+        .green(),
+    );
+    output::set(
+        "removed",
+        serde_json::json!({"kind": entry.kind.to_string(), "display_name": &entry.display_name}),
+    );
+    warn_dangling_pipelines(&config, &entry.display_name);
+    Ok(())
+}
+
+pub fn edit_config_entry(p: &mut dyn Prompter, config_file_name: &str, name: &str) -> Result<()> {
+    ensure(
+        "`mycelial config edit`",
+        "remove the entry with `mycelial config remove` and add it back with `mycelial add`",
+    )?;
+    let mut config = load_config(config_file_name)?;
+    let entry = match config.find_entry(name) {
+        Some(entry) => entry,
+        None => return Err(no_such_entry(config_file_name, name)),
+    };
+    let connector = match connector::registry().find(entry.kind, &entry.section_type) {
+        Some(connector) => connector,
+        None => {
+            return Err(format!(
+                "editing {} type `{}` is not supported, edit `{}` directly",
+                entry.kind, entry.section_type, config_file_name
+            )
+            .into())
+        }
+    };
+    let table = config.entry_table(entry.index).unwrap_or_default();
+    let taken: Vec<String> = config
+        .names_of(entry.kind)
+        .into_iter()
+        .filter(|name| *name != entry.display_name)
+        .collect();
+    let section = prompt_connector(p, connector, &taken, Current(Some(&table)))?;
+    let renamed = section.display_name.clone();
+    config.replace_entry(entry.index, section);
+    let pipelines = match renamed != entry.display_name {
+        true => config.rename_in_pipelines(entry.kind, &entry.display_name, &renamed),
+        false => Vec::new(),
+    };
+    if let Err(error) = config.save(config_file_name) {
+        return Err(Error::Save(format!(
+            "error saving config file `{}`: {}",
+            config_file_name, error
+        ))
+        .into());
+    }
+    output::say(
+        format!(
+            "updated {} `{}` in {}",
+            entry.kind, renamed, config_file_name
+        )
+        .green(),
+    );
+    for pipeline in pipelines.iter() {
+        output::say(format!(
+            "pipeline `{}` now refers to `{}` instead of `{}`",
+            pipeline, renamed, entry.display_name
+        ));
+    }
+    output::set(
+        "updated",
+        serde_json::json!({
+            "kind": entry.kind.to_string(),
+            "display_name": &renamed,
+            "previous_display_name": &entry.display_name,
+            "pipelines": pipelines,
+        }),
+    );
+    Ok(())
+}
+
+// pipelines naming an entry that was removed
+fn warn_dangling_pipelines(config: &Configuration, display_name: &str) {
+    for pipeline in config.pipelines() {
+        if pipeline.source == display_name
+            || pipeline
+                .destinations
+                .iter()
+                .any(|name| name == display_name)
+        {
+            output::warn(format!(
+                "pipeline `{}` still refers to `{}`, update or remove it",
+                pipeline.name, display_name
+            ));
+        }
+    }
+}
+
+fn no_such_entry(config_file_name: &str, name: &str) -> Box<dyn std::error::Error + Send + Sync> {
+    Error::Config(format!(
+        "no source or destination named `{}` in `{}`, see `mycelial config list`",
+        name, config_file_name
+    ))
+    .into()
 }
 
 fn can_start_server() -> bool {
@@ -1,5 +1,12 @@
-use clap::{Parser, Subcommand};
-use mycelial::{add_destination, add_source, destroy, download_binaries, init, reset, start};
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use mycelial::output::{self, Format};
+use mycelial::prompt::{Prompter, Recorder, Script, Terminal};
+use mycelial::{
+    add_destination, add_destination_from_args, add_source, add_source_from_args, destroy,
+    edit_config_entry, exit_code, get_secret, init, list_config, list_secrets, preview_source,
+    quickstart, remove_config_entry, remove_secret, reset, set_mode, set_secret, show_config_entry,
+    start, test_config, update, validate_config, DestinationArgs, Error, Mode, Release, SourceArgs,
+};
 mod service;
 use nix::unistd::Uid;
 use service::Service;
@@ -8,6 +15,22 @@ use service::Service;
 #[command(name = "mycelial")]
 #[command(about = "A command line interface (Cli) for Mycelial", version, long_about = None)]
 struct Cli {
+    /// never prompt: use the values passed as options or their defaults, and
+    /// fail naming the option to pass otherwise
+    #[arg(long, global = true)]
+    non_interactive: bool,
+    /// answer yes to confirmations, implies --non-interactive
+    #[arg(short, long, global = true)]
+    yes: bool,
+    /// write the answers to the prompts to a session file
+    #[arg(long, global = true, value_name = "FILE", conflicts_with = "replay")]
+    record: Option<String>,
+    /// answer the prompts from a session file written with --record
+    #[arg(long, global = true, value_name = "FILE")]
+    replay: Option<String>,
+    /// print text, or a single JSON document once the command is done
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+    output: Format,
     #[command(subcommand)]
     command: Commands,
 }
@@ -22,6 +45,9 @@ enum ServiceCommands {
         /// Installs the daemon as a service
         #[clap(long)]
         daemon: bool,
+        /// vault key file the service reads to resolve `${vault:..}` references
+        #[arg(long)]
+        key_file: Option<String>,
     },
     /// Remove a service
     Remove {
@@ -56,6 +82,101 @@ enum ServiceCommands {
         #[clap(long)]
         daemon: bool,
     },
+    /// Resolve the secrets of the daemon's config and run it, what the
+    /// installed service starts
+    #[command(hide = true)]
+    Run {
+        #[clap(long)]
+        daemon: bool,
+        #[arg(long)]
+        key_file: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum AddCommands {
+    /// add a source from command line options, without prompting
+    Source {
+        #[clap(subcommand)]
+        source: SourceArgs,
+    },
+    /// add a destination from command line options, without prompting
+    Destination {
+        #[clap(subcommand)]
+        destination: DestinationArgs,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommands {
+    /// Check a config file for problems
+    Validate {
+        /// specify a config file name to use
+        #[arg(long)]
+        config: Option<String>,
+    },
+    /// List sources and destinations
+    List {
+        /// specify a config file name to use
+        #[arg(long)]
+        config: Option<String>,
+    },
+    /// Show a source or destination
+    Show {
+        /// display name or index (see `config list`)
+        name: String,
+        /// specify a config file name to use
+        #[arg(long)]
+        config: Option<String>,
+    },
+    /// Edit a source or destination, answering prompts pre-filled with its current values
+    Edit {
+        /// display name or index (see `config list`)
+        name: String,
+        /// specify a config file name to use
+        #[arg(long)]
+        config: Option<String>,
+    },
+    /// Remove a source or destination
+    Remove {
+        /// display name or index (see `config list`)
+        name: String,
+        /// specify a config file name to use
+        #[arg(long)]
+        config: Option<String>,
+    },
+    /// Test the connection of a source or destination, or of all of them
+    Test {
+        /// display name or index (see `config list`)
+        name: Option<String>,
+        /// specify a config file name to use
+        #[arg(long)]
+        config: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum SecretsCommands {
+    /// Store a secret, prompting for its value unless --value is given
+    Set {
+        /// name used in `${vault:NAME}` references
+        name: String,
+        /// secret value
+        #[arg(long)]
+        value: Option<String>,
+    },
+    /// Print a secret
+    Get {
+        /// secret name
+        name: String,
+    },
+    /// List secret names
+    List,
+    /// Remove a secret
+    Rm {
+        /// secret name
+        name: String,
+    },
 }
 
 #[derive(Debug, Subcommand)]
@@ -80,6 +201,12 @@ enum Commands {
         /// workspace token
         #[arg(short, long)]
         token: Option<String>,
+        /// read every answer from a TOML file (`-` for stdin) instead of prompting
+        #[arg(long)]
+        answers: Option<String>,
+        /// download this release (vX.Y.Z) and pin it, or `latest` to unpin
+        #[arg(long, value_name = "VERSION")]
+        version: Option<Release>,
     },
     /// starts the daemon and control plane
     Start {
@@ -92,6 +219,21 @@ enum Commands {
         /// specify a config file name to use
         #[arg(long)]
         config: Option<String>,
+        /// control plane security token, asked for when not given
+        #[arg(long)]
+        token: Option<String>,
+    },
+    /// set up and start a demo pipeline replicating sample data
+    Quickstart {
+        /// use hello world connectors instead of a sample SQLite database
+        #[arg(long)]
+        hello_world: bool,
+        /// write the sample data and config without downloading or starting anything
+        #[arg(long)]
+        no_start: bool,
+        /// specify a config file name to use
+        #[arg(long)]
+        config: Option<String>,
     },
     /// stops the daemon and control plane
     Destroy {
@@ -123,9 +265,44 @@ enum Commands {
         /// add a destination to config
         destination: bool,
         /// specify a config file name to use
+        #[arg(long, global = true)]
+        config: Option<String>,
+        /// test the connection before saving
+        #[arg(long, global = true)]
+        check: bool,
+        /// save even if the connection test fails, needs --check
+        #[arg(long, global = true)]
+        save_anyway: bool,
+        #[clap(subcommand)]
+        connector: Option<AddCommands>,
+    },
+    /// inspect and manage the config file
+    Config {
+        #[clap(subcommand)]
+        action: ConfigCommands,
+    },
+    /// show the first rows a source reads
+    Preview {
+        /// display name or index of a source (see `config list`)
+        name: String,
+        /// number of rows to show
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+        /// print each row as a JSON object on its own line instead of a table
+        #[arg(long)]
+        json_lines: bool,
+        /// specify a config file name to use
         #[arg(long)]
         config: Option<String>,
     },
+    /// manage the encrypted secrets vault (~/.mycelial/vault)
+    Secrets {
+        /// read the vault key from a file instead of asking for a passphrase
+        #[arg(long, global = true)]
+        key_file: Option<String>,
+        #[clap(subcommand)]
+        action: SecretsCommands,
+    },
     /// install mycelial as a service
     Service {
         #[clap(subcommand)]
@@ -139,22 +316,133 @@ enum Commands {
         /// update the control plane
         #[arg(short, long)]
         control_plane: bool,
+        /// update to this release (vX.Y.Z) and pin it, or `latest` to unpin
+        #[arg(long, value_name = "VERSION")]
+        version: Option<Release>,
     },
 }
 
 #[tokio::main]
 async fn main() {
-    let args = Cli::parse();
-    match run(args).await {
-        Ok(_) => {}
-        Err(e) => {
+    let matches = Cli::command()
+        .try_get_matches()
+        .unwrap_or_else(|e| usage_error(None, e));
+    let command = command_name(&matches);
+    let args = Cli::from_arg_matches(&matches)
+        .and_then(check_args)
+        .unwrap_or_else(|e| usage_error(Some(&command), e));
+    output::set_format(args.output);
+    let result = run(args).await;
+    output::finish(&command, &result);
+    if let Err(e) = result {
+        if !output::json() {
             eprintln!("Error: {}", e);
-            std::process::exit(1);
         }
+        std::process::exit(exit_code(e.as_ref()));
     }
 }
 
+// rules clap can't check: global flags may be given on either side of a
+// subcommand, but `requires` only sees the ones given after it
+fn check_args(args: Cli) -> Result<Cli, clap::Error> {
+    if let Commands::Add {
+        check: false,
+        save_anyway: true,
+        ..
+    } = args.command
+    {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::MissingRequiredArgument,
+            "--save-anyway can only be used with --check",
+        ));
+    }
+    if let Commands::Preview {
+        json_lines: true, ..
+    } = args.command
+    {
+        if args.output == Format::Json {
+            return Err(Cli::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--json-lines can't be used with --output json",
+            ));
+        }
+    }
+    Ok(args)
+}
+
+// clap prints its errors as text and exits, with `--output json` they are
+// reported in the document like any other failure; help and version are
+// still printed as they are
+fn usage_error(command: Option<&str>, error: clap::Error) -> ! {
+    let json = std::env::args()
+        .skip(1)
+        .scan(false, |after_flag, arg| {
+            let requested = (*after_flag && arg == "json") || arg == "--output=json";
+            *after_flag = arg == "--output";
+            Some(requested)
+        })
+        .any(|requested| requested);
+    if !json || !error.use_stderr() {
+        error.exit()
+    }
+    // the rendered error on one line, without the usage, tips and hints
+    let rendered = error.to_string();
+    let message = rendered
+        .lines()
+        .map(str::trim)
+        .take_while(|line| !line.starts_with("Usage:"))
+        .filter(|line| !line.is_empty() && !line.starts_with("tip:"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let message = message.strip_prefix("error: ").unwrap_or(&message);
+    output::set_format(Format::Json);
+    let error = Error::Usage(message.to_string());
+    let code = error.exit_code();
+    let command = command.map_or_else(requested_command, str::to_string);
+    output::finish(&command, &Err(error.into()));
+    std::process::exit(code)
+}
+
+/// The subcommands named on the command line when it could not be parsed,
+/// as far as they are known.
+fn requested_command() -> String {
+    let mut names = Vec::new();
+    let mut command = Cli::command();
+    for arg in std::env::args().skip(1) {
+        if let Some(subcommand) = command.find_subcommand(&arg).cloned() {
+            names.push(subcommand.get_name().to_string());
+            command = subcommand;
+            // only the subcommands named are built, `add source` lists its
+            // connectors once built
+            command.build();
+        }
+    }
+    names.join(" ")
+}
+
+/// The subcommands that were run, such as `config list`.
+fn command_name(matches: &ArgMatches) -> String {
+    let mut names = Vec::new();
+    let mut matches = matches;
+    while let Some((name, subcommand)) = matches.subcommand() {
+        names.push(name);
+        matches = subcommand;
+    }
+    names.join(" ")
+}
+
 async fn run(args: Cli) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if args.yes {
+        set_mode(Mode::AssumeYes);
+    } else if args.non_interactive {
+        set_mode(Mode::NonInteractive);
+    }
+    let mut prompter: Box<dyn Prompter> = match (args.record, args.replay) {
+        (_, Some(replay)) => Box::new(Script::load(&replay)?),
+        (Some(record), None) => Box::new(Recorder::new(Box::new(Terminal), record)),
+        (None, None) => Box::new(Terminal),
+    };
+    let p = prompter.as_mut();
     match args.command {
         Commands::Init {
             local,
@@ -163,23 +451,56 @@ async fn run(args: Cli) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
             config,
             endpoint,
             token,
+            answers,
+            version,
         } => {
             let config_file_name = match config {
                 Some(config) => config,
                 None => "config.toml".to_string(),
             };
             if local {
-                init(true, true, config_file_name, endpoint, token).await?;
+                init(
+                    p,
+                    true,
+                    true,
+                    config_file_name,
+                    endpoint,
+                    token,
+                    answers,
+                    version,
+                )
+                .await?;
             } else if daemon || control_plane {
-                init(daemon, control_plane, config_file_name, endpoint, token).await?;
+                init(
+                    p,
+                    daemon,
+                    control_plane,
+                    config_file_name,
+                    endpoint,
+                    token,
+                    answers,
+                    version,
+                )
+                .await?;
             } else {
-                init(false, false, config_file_name, endpoint, token).await?;
+                init(
+                    p,
+                    false,
+                    false,
+                    config_file_name,
+                    endpoint,
+                    token,
+                    answers,
+                    version,
+                )
+                .await?;
             }
         }
         Commands::Start {
             daemon,
             control_plane,
             config,
+            token,
         } => {
             let config_file_name = match config {
                 Some(config) => config,
@@ -187,11 +508,22 @@ async fn run(args: Cli) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
             };
             // if neither daemon or control_plane are specified, start both
             if !daemon && !control_plane {
-                start(true, true, config_file_name).await?;
+                start(p, true, true, config_file_name, token).await?;
             } else {
-                start(daemon, control_plane, config_file_name).await?;
+                start(p, daemon, control_plane, config_file_name, token).await?;
             }
         }
+        Commands::Quickstart {
+            hello_world,
+            no_start,
+            config,
+        } => {
+            let config_file_name = match config {
+                Some(config) => config,
+                None => "config.toml".to_string(),
+            };
+            quickstart(p, &config_file_name, hello_world, no_start).await?;
+        }
         Commands::Destroy {
             daemon,
             control_plane,
@@ -214,13 +546,13 @@ async fn run(args: Cli) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
             };
             // if neither daemon or control_plane are specified, destroy both
             if !daemon && !control_plane {
-                reset(true, true, &config_file_name).await?;
+                reset(p, true, true, &config_file_name).await?;
             } else {
                 if daemon {
-                    reset(true, false, &config_file_name).await?;
+                    reset(p, true, false, &config_file_name).await?;
                 }
                 if control_plane {
-                    reset(false, true, &config_file_name).await?;
+                    reset(p, false, true, &config_file_name).await?;
                 }
             }
         }
@@ -228,47 +560,140 @@ async fn run(args: Cli) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
             source,
             destination,
             config,
+            check,
+            save_anyway,
+            connector,
         } => {
             let config_file_name = match config {
                 Some(config) => config,
                 None => "config.toml".to_string(),
             };
+            match connector {
+                Some(AddCommands::Source { source }) => {
+                    return add_source_from_args(p, &config_file_name, source, check, save_anyway);
+                }
+                Some(AddCommands::Destination { destination }) => {
+                    return add_destination_from_args(
+                        p,
+                        &config_file_name,
+                        destination,
+                        check,
+                        save_anyway,
+                    );
+                }
+                None => {}
+            }
             if !source && !destination {
-                return Err(
-                    "add command must be run with the --source and/or --destination options".into(),
-                );
+                return Err(Error::Usage(
+                    "add command must be run with the --source and/or --destination options"
+                        .to_string(),
+                )
+                .into());
             }
             if source {
-                add_source(&config_file_name).await?;
+                add_source(p, &config_file_name, check, save_anyway).await?;
             }
             if destination {
-                add_destination(&config_file_name).await?;
+                add_destination(p, &config_file_name, check, save_anyway).await?;
+            }
+        }
+        Commands::Config { action } => match action {
+            ConfigCommands::Validate { config } => {
+                let config_file_name = match config {
+                    Some(config) => config,
+                    None => "config.toml".to_string(),
+                };
+                validate_config(&config_file_name)?;
+            }
+            ConfigCommands::List { config } => {
+                let config_file_name = match config {
+                    Some(config) => config,
+                    None => "config.toml".to_string(),
+                };
+                list_config(&config_file_name)?;
+            }
+            ConfigCommands::Show { name, config } => {
+                let config_file_name = match config {
+                    Some(config) => config,
+                    None => "config.toml".to_string(),
+                };
+                show_config_entry(&config_file_name, &name)?;
+            }
+            ConfigCommands::Edit { name, config } => {
+                let config_file_name = match config {
+                    Some(config) => config,
+                    None => "config.toml".to_string(),
+                };
+                edit_config_entry(p, &config_file_name, &name)?;
+            }
+            ConfigCommands::Remove { name, config } => {
+                let config_file_name = match config {
+                    Some(config) => config,
+                    None => "config.toml".to_string(),
+                };
+                remove_config_entry(&config_file_name, &name)?;
+            }
+            ConfigCommands::Test { name, config } => {
+                let config_file_name = match config {
+                    Some(config) => config,
+                    None => "config.toml".to_string(),
+                };
+                test_config(p, &config_file_name, name.as_deref())?;
+            }
+        },
+        Commands::Preview {
+            name,
+            limit,
+            json_lines,
+            config,
+        } => {
+            let config_file_name = match config {
+                Some(config) => config,
+                None => "config.toml".to_string(),
+            };
+            preview_source(p, &config_file_name, &name, limit, json_lines)?;
+        }
+        Commands::Secrets { key_file, action } => {
+            let key_file = key_file.as_deref();
+            match action {
+                SecretsCommands::Set { name, value } => set_secret(p, &name, value, key_file)?,
+                SecretsCommands::Get { name } => get_secret(p, &name, key_file)?,
+                SecretsCommands::List => list_secrets(p, key_file)?,
+                SecretsCommands::Rm { name } => remove_secret(p, &name, key_file)?,
             }
         }
         Commands::Update {
             daemon,
             control_plane,
+            version,
         } => {
             if !daemon && !control_plane {
-                return Err(
+                return Err(Error::Usage(
                     "update command must be run with the --daemon and/or --control-plane options"
-                        .into(),
-                );
+                        .to_string(),
+                )
+                .into());
             }
-            download_binaries(daemon, control_plane).await?;
-            println!("Update complete");
+            update(p, daemon, control_plane, version).await?;
         }
         Commands::Service { action } => {
             if !Uid::effective().is_root() {
-                return Err("You must run this command with root permissions(sudo)".into());
+                return Err(Error::Service(
+                    "You must run this command with root permissions(sudo)".to_string(),
+                )
+                .into());
             }
             match action {
-                ServiceCommands::Add { config, daemon } => {
+                ServiceCommands::Add {
+                    config,
+                    daemon,
+                    key_file,
+                } => {
                     if daemon {
                         let service = Service::new();
-                        service.add_client(config).await?;
+                        service.add_client(p, config, key_file).await?;
                     } else {
-                        println!("--daemon not specified");
+                        output::warn("--daemon not specified");
                     }
                 }
                 ServiceCommands::Remove { daemon, purge } => {
@@ -276,7 +701,7 @@ async fn run(args: Cli) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
                         let service = Service::new();
                         service.remove_client(purge).await?;
                     } else {
-                        println!("--daemon not specified");
+                        output::warn("--daemon not specified");
                     }
                 }
                 ServiceCommands::Status { daemon } => {
@@ -285,7 +710,7 @@ async fn run(args: Cli) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
                         service.status_client()?;
                     }
                     if !daemon {
-                        println!("--daemon not specified");
+                        output::warn("--daemon not specified");
                     }
                 }
                 ServiceCommands::Start { daemon } => {
@@ -294,7 +719,7 @@ async fn run(args: Cli) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
                         service.start_client()?;
                     }
                     if !daemon {
-                        println!("--daemon not specified");
+                        output::warn("--daemon not specified");
                     }
                 }
                 ServiceCommands::Stop { daemon } => {
@@ -303,7 +728,7 @@ async fn run(args: Cli) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
                         service.stop_client()?;
                     }
                     if !daemon {
-                        println!("--daemon not specified");
+                        output::warn("--daemon not specified");
                     }
                 }
                 ServiceCommands::Restart { daemon } => {
@@ -312,7 +737,16 @@ async fn run(args: Cli) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
                         service.restart_client()?;
                     }
                     if !daemon {
-                        println!("--daemon not specified");
+                        output::warn("--daemon not specified");
+                    }
+                }
+                ServiceCommands::Run { daemon, key_file } => {
+                    let service = Service::new();
+                    if daemon {
+                        service.run_client(p, key_file.as_deref())?;
+                    }
+                    if !daemon {
+                        output::warn("--daemon not specified");
                     }
                 }
             }
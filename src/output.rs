@@ -0,0 +1,122 @@
+//! How commands report what they did, set once from `--output`.
+//!
+//! As text, messages are printed as they happen. As JSON nothing else is
+//! printed, colors and progress bars are off, and what the command records
+//! with [`set`] and [`push`] is printed as one document when it is done:
+//!
+//! ```json
+//! {"command": "destroy", "ok": true, "killed": [{"process": "daemon", "pid": 1234}]}
+//! ```
+//!
+//! Failed commands have `"ok": false` and an `error` with its message and
+//! exit code.
+
+use crate::error;
+use colored::*;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+static FORMAT: AtomicU8 = AtomicU8::new(Format::Text as u8);
+static REPORT: Mutex<Option<Map<String, Value>>> = Mutex::new(None);
+
+/// Sets how commands report for the rest of the process.
+pub fn set_format(format: Format) {
+    FORMAT.store(format as u8, Ordering::Relaxed);
+    if format == Format::Json {
+        colored::control::set_override(false);
+    }
+}
+
+/// True when reporting a JSON document.
+pub fn json() -> bool {
+    FORMAT.load(Ordering::Relaxed) == Format::Json as u8
+}
+
+/// Prints a line, only as text.
+pub fn say(line: impl Display) {
+    if !json() {
+        println!("{}", line);
+    }
+}
+
+/// Prints a line for whoever answers the prompts, on stderr with JSON so the
+/// document stays the only output.
+pub fn note(line: impl Display) {
+    match json() {
+        true => eprintln!("{}", line),
+        false => println!("{}", line),
+    }
+}
+
+/// Warns about something that didn't stop the command, listed under
+/// `warnings` in JSON.
+pub fn warn(message: impl Display) {
+    match json() {
+        true => push("warnings", message.to_string()),
+        false => println!("{}", message.to_string().yellow()),
+    }
+}
+
+fn with_report(update: impl FnOnce(&mut Map<String, Value>)) {
+    let mut report = match REPORT.lock() {
+        Ok(report) => report,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    update(report.get_or_insert_with(Map::new));
+}
+
+/// Records `value` under `key` of the JSON document.
+pub fn set(key: &str, value: impl Serialize) {
+    let value = serde_json::to_value(value).unwrap_or(Value::Null);
+    with_report(|report| {
+        report.insert(key.to_string(), value);
+    });
+}
+
+/// Appends `value` to the list under `key` of the JSON document.
+pub fn push(key: &str, value: impl Serialize) {
+    let value = serde_json::to_value(value).unwrap_or(Value::Null);
+    with_report(|report| {
+        match report
+            .entry(key.to_string())
+            .or_insert_with(|| Value::Array(Vec::new()))
+        {
+            Value::Array(values) => values.push(value),
+            other => *other = Value::Array(vec![other.take(), value]),
+        }
+    });
+}
+
+/// Prints the JSON document of `command`, with its error if it failed.
+/// Nothing is printed as text.
+pub fn finish(
+    command: &str,
+    result: &std::result::Result<(), Box<dyn std::error::Error + Send + Sync>>,
+) {
+    if !json() {
+        return;
+    }
+    let mut document = Map::new();
+    document.insert("command".to_string(), command.into());
+    document.insert("ok".to_string(), result.is_ok().into());
+    with_report(|report| document.append(report));
+    if let Err(error) = result {
+        document.insert(
+            "error".to_string(),
+            serde_json::json!({
+                "message": error.to_string(),
+                "exit_code": error::exit_code(error.as_ref()),
+            }),
+        );
+    }
+    println!("{}", Value::Object(document));
+}
@@ -0,0 +1,240 @@
+//! Sample rows of a source, for `mycelial preview`.
+//!
+//! The rows are read with the fields stored in the config, the query of a
+//! database source is run as is, wrapped in a `limit`.
+
+use crate::check::{self, describe};
+use crate::connector::{Connector, PreviewType};
+use crate::prompt::Prompter;
+use crate::secrets;
+use calamine::{Data, Reader};
+use serde_json::Value;
+use std::io::{BufRead, BufReader};
+
+/// Rows of a query, or of one sheet of a workbook.
+pub(crate) struct Sample {
+    /// sheet name, when a workbook has more than one sheet selected
+    pub title: Option<String>,
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl Sample {
+    fn new(columns: Vec<String>) -> Sample {
+        Sample {
+            title: None,
+            columns,
+            rows: Vec::new(),
+        }
+    }
+}
+
+/// Reads up to `limit` rows of the source described by `table`.
+pub(crate) fn run(
+    p: &mut dyn Prompter,
+    connector: &Connector,
+    table: &toml::Table,
+    limit: usize,
+) -> Result<Vec<Sample>, String> {
+    let preview = connector.preview.as_ref().ok_or_else(|| {
+        format!(
+            "{} {}s can't be previewed",
+            connector.section_type, connector.kind
+        )
+    })?;
+    let mut resolve = |name: &str, url: bool| {
+        secrets::resolve_value(p, &connector.value_of(name, table), url)
+            .map_err(|error| error.to_string())
+    };
+    let url = matches!(
+        preview.preview_type,
+        PreviewType::Postgres | PreviewType::Mysql
+    );
+    let target = resolve(&preview.field, url)?;
+    let query = match &preview.query {
+        Some(query) => limited(&resolve(query, false)?, limit),
+        None => String::new(),
+    };
+    match preview.preview_type {
+        PreviewType::Sqlite => sqlite(&target, &query).map(|sample| vec![sample]),
+        PreviewType::Postgres => check::block_on(postgres(&target, &query)).map(|s| vec![s]),
+        PreviewType::Mysql => check::block_on(mysql(&target, &query)).map(|s| vec![s]),
+        PreviewType::Excel => {
+            let sheets = match &preview.sheets {
+                Some(sheets) => resolve(sheets, false)?,
+                None => "*".to_string(),
+            };
+            excel(&target, &sheets, limit)
+        }
+        PreviewType::File => file(&target, limit).map(|sample| vec![sample]),
+    }
+}
+
+// wraps the query of a source so it stops after `limit` rows
+fn limited(query: &str, limit: usize) -> String {
+    let query = query.trim().trim_end_matches(';');
+    format!("select * from ({}) as preview limit {}", query, limit)
+}
+
+fn number(value: f64) -> Value {
+    serde_json::Number::from_f64(value)
+        .map(Value::Number)
+        .unwrap_or(Value::Null)
+}
+
+fn sqlite(path: &str, query: &str) -> Result<Sample, String> {
+    use rusqlite::types::ValueRef;
+    use rusqlite::{Connection, OpenFlags};
+
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|error| format!("can't open `{}`: {}", path, error))?;
+    let mut statement = connection
+        .prepare(query)
+        .map_err(|error| error.to_string())?;
+    let mut sample = Sample::new(
+        statement
+            .column_names()
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+    );
+    let width = sample.columns.len();
+    let mut rows = statement.query([]).map_err(|error| error.to_string())?;
+    while let Some(row) = rows.next().map_err(|error| error.to_string())? {
+        let mut values = Vec::with_capacity(width);
+        for index in 0..width {
+            let value = match row.get_ref(index).map_err(|error| error.to_string())? {
+                ValueRef::Null => Value::Null,
+                ValueRef::Integer(value) => Value::from(value),
+                ValueRef::Real(value) => number(value),
+                ValueRef::Text(text) => Value::from(String::from_utf8_lossy(text)),
+                ValueRef::Blob(blob) => Value::from(format!("<{} bytes>", blob.len())),
+            };
+            values.push(value);
+        }
+        sample.rows.push(values);
+    }
+    Ok(sample)
+}
+
+async fn postgres(url: &str, query: &str) -> Result<Sample, String> {
+    use tokio_postgres::SimpleQueryMessage;
+
+    let client = check::postgres_client(url).await?;
+    let messages = client
+        .simple_query(query)
+        .await
+        .map_err(|error| describe(&error))?;
+    let mut sample = Sample::new(Vec::new());
+    for message in messages {
+        match message {
+            SimpleQueryMessage::RowDescription(columns) => {
+                sample.columns = columns.iter().map(|c| c.name().to_string()).collect();
+            }
+            // the simple query protocol returns every value as text
+            SimpleQueryMessage::Row(row) => sample.rows.push(
+                (0..row.len())
+                    .map(|index| row.get(index).map(Value::from).unwrap_or(Value::Null))
+                    .collect(),
+            ),
+            _ => {}
+        }
+    }
+    Ok(sample)
+}
+
+async fn mysql(url: &str, query: &str) -> Result<Sample, String> {
+    use mysql_async::prelude::Queryable;
+    use mysql_async::Row;
+
+    let mut conn = check::mysql_conn(url).await?;
+    let mut result = conn
+        .query_iter(query)
+        .await
+        .map_err(|error| describe(&error))?;
+    let mut sample = Sample::new(
+        result
+            .columns()
+            .map(|columns| columns.iter().map(|c| c.name_str().to_string()).collect())
+            .unwrap_or_default(),
+    );
+    let rows: Vec<Row> = result.collect().await.map_err(|error| describe(&error))?;
+    drop(result);
+    let _ = conn.disconnect().await;
+    for row in rows {
+        sample
+            .rows
+            .push(row.unwrap().into_iter().map(mysql_value).collect());
+    }
+    Ok(sample)
+}
+
+fn mysql_value(value: mysql_async::Value) -> Value {
+    use mysql_async::Value as Sql;
+
+    match value {
+        Sql::NULL => Value::Null,
+        Sql::Bytes(bytes) => Value::from(String::from_utf8_lossy(&bytes)),
+        Sql::Int(value) => Value::from(value),
+        Sql::UInt(value) => Value::from(value),
+        Sql::Float(value) => number(value.into()),
+        Sql::Double(value) => number(value),
+        other => Value::from(other.as_sql(true).trim_matches('\'')),
+    }
+}
+
+fn excel_value(cell: &Data) -> Value {
+    match cell {
+        Data::Empty => Value::Null,
+        Data::Int(value) => Value::from(*value),
+        Data::Float(value) => number(*value),
+        Data::Bool(value) => Value::from(*value),
+        Data::String(value) => Value::from(value.as_str()),
+        other => Value::from(other.to_string()),
+    }
+}
+
+// the first row of a sheet is its header
+fn excel(path: &str, sheets: &str, limit: usize) -> Result<Vec<Sample>, String> {
+    let mut workbook = calamine::open_workbook_auto(path)
+        .map_err(|error| format!("can't open `{}`: {}", path, error))?;
+    let names: Vec<String> = match sheets.trim() {
+        "*" => workbook.sheet_names(),
+        sheets => sheets
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .collect(),
+    };
+    let mut samples = Vec::with_capacity(names.len());
+    for name in names.iter() {
+        let range = workbook
+            .worksheet_range(name)
+            .map_err(|error| format!("can't read sheet `{}` of `{}`: {}", name, path, error))?;
+        let mut rows = range.rows();
+        let columns = rows
+            .next()
+            .map(|header| header.iter().map(ToString::to_string).collect())
+            .unwrap_or_default();
+        let mut sample = Sample::new(columns);
+        sample.rows = rows
+            .take(limit)
+            .map(|row| row.iter().map(excel_value).collect())
+            .collect();
+        if names.len() > 1 {
+            sample.title = Some(name.clone());
+        }
+        samples.push(sample);
+    }
+    Ok(samples)
+}
+
+fn file(path: &str, limit: usize) -> Result<Sample, String> {
+    let file =
+        std::fs::File::open(path).map_err(|error| format!("can't read `{}`: {}", path, error))?;
+    let mut sample = Sample::new(vec!["line".to_string()]);
+    for line in BufReader::new(file).lines().take(limit) {
+        let line = line.map_err(|error| format!("can't read `{}`: {}", path, error))?;
+        sample.rows.push(vec![Value::from(line)]);
+    }
+    Ok(sample)
+}
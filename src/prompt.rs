@@ -0,0 +1,447 @@
+//! Where the answers to prompts come from: the terminal, a script of
+//! answers, or the terminal while the answers are recorded to a session file
+//! that `--replay` reads back.
+//!
+//! A session file lists the answers in the order the prompts were asked:
+//!
+//! ```toml
+//! [[answers]]
+//! prompt = "Daemon Name:"
+//! answer = "My Daemon"
+//!
+//! [[answers]]
+//! prompt = "What would you like to do?"
+//! answer = "Exit"
+//! ```
+//!
+//! Selections are answered with the text of the item, multiple selections
+//! with a list of them and confirmations with `true` or `false`. Passwords
+//! are only recorded when they are secret references.
+
+use crate::interactive::{self, Mode};
+use crate::{secrets, Error, Result};
+use dialoguer::{theme::ColorfulTheme, Confirm, FuzzySelect, Input, MultiSelect, Password};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+/// Checks an answer, the error is shown before asking again.
+pub type Validator<'a> = &'a dyn Fn(&str) -> std::result::Result<(), String>;
+
+/// A question answered with text.
+pub struct Text<'a> {
+    pub prompt: String,
+    /// answer given when the input is left empty
+    pub default: Option<String>,
+    /// text the input starts with
+    pub initial: Option<String>,
+    pub allow_empty: bool,
+    pub validate: Option<Validator<'a>>,
+    /// flag or option that gives the answer instead, named when the question
+    /// can't be asked
+    pub flag: Option<String>,
+}
+
+impl<'a> Text<'a> {
+    pub fn new(prompt: impl Into<String>) -> Text<'a> {
+        Text {
+            prompt: prompt.into(),
+            default: None,
+            initial: None,
+            allow_empty: false,
+            validate: None,
+            flag: None,
+        }
+    }
+
+    pub fn default(mut self, default: impl Into<String>) -> Text<'a> {
+        self.default = Some(default.into());
+        self
+    }
+
+    pub fn initial(mut self, initial: impl Into<String>) -> Text<'a> {
+        self.initial = Some(initial.into());
+        self
+    }
+
+    pub fn allow_empty(mut self, allow_empty: bool) -> Text<'a> {
+        self.allow_empty = allow_empty;
+        self
+    }
+
+    pub fn validate(mut self, validate: Validator<'a>) -> Text<'a> {
+        self.validate = Some(validate);
+        self
+    }
+
+    pub fn flag(mut self, flag: impl Into<String>) -> Text<'a> {
+        self.flag = Some(flag.into());
+        self
+    }
+
+    // what an answer stands for, with the same rules as the terminal
+    fn check(&self, answer: &str) -> std::result::Result<String, String> {
+        let answer = match (answer.is_empty(), &self.default) {
+            (true, Some(default)) => default.clone(),
+            _ => answer.to_string(),
+        };
+        if answer.is_empty() && !self.allow_empty {
+            return Err("an answer is required".to_string());
+        }
+        match self.validate {
+            Some(validate) => validate(&answer).map(|_| answer),
+            None => Ok(answer),
+        }
+    }
+}
+
+/// Asks the questions of the config wizard.
+pub trait Prompter {
+    fn input(&mut self, text: Text) -> Result<String>;
+    /// Like `input`, without echoing the answer.
+    fn password(&mut self, text: Text) -> Result<String>;
+    /// Without a `default` the question must be answered.
+    fn confirm(&mut self, prompt: &str, default: Option<bool>) -> Result<bool>;
+    /// Returns the index of the chosen item.
+    fn select(&mut self, prompt: &str, items: &[&str], default: Option<usize>) -> Result<usize>;
+    /// Returns the indexes of the chosen items, `defaults` are the ones
+    /// chosen to begin with.
+    fn multi_select(
+        &mut self,
+        prompt: &str,
+        items: &[&str],
+        defaults: &[bool],
+    ) -> Result<Vec<usize>>;
+}
+
+/// Prompts on the terminal, unless `--non-interactive` is set.
+pub struct Terminal;
+
+impl Terminal {
+    fn allowed(&self, prompt: &str, flag: Option<&str>) -> Result<ColorfulTheme> {
+        match (interactive::mode(), flag) {
+            (Mode::Interactive, _) => Ok(ColorfulTheme::default()),
+            (_, Some(flag)) => Err(Error::Prompt(format!(
+                "`{}` can't be asked with --non-interactive, pass {}",
+                prompt, flag
+            ))
+            .into()),
+            (_, None) => Err(Error::Prompt(format!(
+                "`{}` can't be asked with --non-interactive",
+                prompt
+            ))
+            .into()),
+        }
+    }
+}
+
+impl Prompter for Terminal {
+    fn input(&mut self, text: Text) -> Result<String> {
+        let theme = self.allowed(&text.prompt, text.flag.as_deref())?;
+        let mut input = Input::<String>::with_theme(&theme)
+            .with_prompt(&text.prompt)
+            .allow_empty(text.allow_empty);
+        if let Some(default) = text.default {
+            input = input.default(default);
+        }
+        if let Some(initial) = text.initial {
+            input = input.with_initial_text(initial);
+        }
+        if let Some(validate) = text.validate {
+            input = input.validate_with(move |input: &String| validate(input));
+        }
+        Ok(input.interact_text().map_err(Error::from)?)
+    }
+
+    fn password(&mut self, text: Text) -> Result<String> {
+        let theme = self.allowed(&text.prompt, text.flag.as_deref())?;
+        let mut password = Password::with_theme(&theme)
+            .with_prompt(&text.prompt)
+            .allow_empty_password(text.allow_empty);
+        if let Some(validate) = text.validate {
+            password = password.validate_with(move |input: &String| validate(input));
+        }
+        Ok(password.interact().map_err(Error::from)?)
+    }
+
+    fn confirm(&mut self, prompt: &str, default: Option<bool>) -> Result<bool> {
+        match (interactive::mode(), default) {
+            (Mode::Interactive, _) => {}
+            (Mode::AssumeYes, _) => return Ok(true),
+            (Mode::NonInteractive, Some(default)) => return Ok(default),
+            (Mode::NonInteractive, None) => {
+                return Err(Error::Prompt(format!(
+                    "`{}` needs an answer, pass --yes to confirm",
+                    prompt
+                ))
+                .into())
+            }
+        }
+        let theme = ColorfulTheme::default();
+        let mut confirm = Confirm::with_theme(&theme)
+            .with_prompt(prompt)
+            .wait_for_newline(true);
+        if let Some(default) = default {
+            confirm = confirm.default(default);
+        }
+        Ok(confirm.interact().map_err(Error::from)?)
+    }
+
+    fn select(&mut self, prompt: &str, items: &[&str], default: Option<usize>) -> Result<usize> {
+        let theme = self.allowed(prompt, None)?;
+        let mut select = FuzzySelect::with_theme(&theme)
+            .with_prompt(prompt)
+            .items(items);
+        if let Some(default) = default {
+            select = select.default(default);
+        }
+        Ok(select.interact().map_err(Error::from)?)
+    }
+
+    fn multi_select(
+        &mut self,
+        prompt: &str,
+        items: &[&str],
+        defaults: &[bool],
+    ) -> Result<Vec<usize>> {
+        let theme = self.allowed(prompt, None)?;
+        Ok(MultiSelect::with_theme(&theme)
+            .with_prompt(prompt)
+            .items(items)
+            .defaults(defaults)
+            .interact()
+            .map_err(Error::from)?)
+    }
+}
+
+/// A recorded answer. Secrets that aren't references have no answer.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Answer {
+    pub prompt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answer: Option<toml::Value>,
+}
+
+impl Answer {
+    pub fn new(prompt: impl Into<String>, answer: impl Into<toml::Value>) -> Answer {
+        Answer {
+            prompt: prompt.into(),
+            answer: Some(answer.into()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Session {
+    #[serde(default)]
+    answers: Vec<Answer>,
+}
+
+/// Answers prompts from a list, in order. Each answer must be for the
+/// prompt being asked, and pass the checks a typed answer would.
+pub struct Script {
+    name: String,
+    answers: VecDeque<Answer>,
+}
+
+impl Script {
+    pub fn new(answers: impl IntoIterator<Item = Answer>) -> Script {
+        Script {
+            name: "script".to_string(),
+            answers: answers.into_iter().collect(),
+        }
+    }
+
+    /// Reads the answers of a session file.
+    pub fn load(path: &str) -> Result<Script> {
+        let contents = std::fs::read_to_string(path).map_err(|error| {
+            Error::Prompt(format!("could not read session file `{}`: {}", path, error))
+        })?;
+        let session: Session = toml::from_str(&contents).map_err(|error| {
+            Error::Prompt(format!(
+                "could not parse session file `{}`: {}",
+                path,
+                error.message()
+            ))
+        })?;
+        Ok(Script {
+            name: format!("session file `{}`", path),
+            answers: session.answers.into(),
+        })
+    }
+
+    /// Answers that weren't asked for.
+    pub fn remaining(&self) -> usize {
+        self.answers.len()
+    }
+
+    fn next(&mut self, prompt: &str) -> Result<toml::Value> {
+        let answer = match self.answers.pop_front() {
+            Some(answer) => answer,
+            None => {
+                return Err(
+                    Error::Prompt(format!("{} has no answer for `{}`", self.name, prompt)).into(),
+                )
+            }
+        };
+        if answer.prompt.trim() != prompt.trim() {
+            return Err(Error::Prompt(format!(
+                "{} answers `{}` where `{}` is asked",
+                self.name, answer.prompt, prompt
+            ))
+            .into());
+        }
+        answer.answer.ok_or_else(|| {
+            Error::Prompt(format!(
+                "{} has no answer for `{}`, secrets are only recorded as references",
+                self.name, prompt
+            ))
+            .into()
+        })
+    }
+
+    fn invalid(&self, prompt: &str, answer: &toml::Value, error: &str) -> Error {
+        Error::Prompt(format!(
+            "{}: `{}` is not an answer to `{}`: {}",
+            self.name, answer, prompt, error
+        ))
+    }
+
+    fn text(&mut self, text: Text) -> Result<String> {
+        let value = self.next(&text.prompt)?;
+        let answer = match &value {
+            toml::Value::String(answer) => answer.clone(),
+            toml::Value::Integer(_) | toml::Value::Boolean(_) => value.to_string(),
+            _ => return Err(self.invalid(&text.prompt, &value, "expected text").into()),
+        };
+        text.check(&answer)
+            .map_err(|error| self.invalid(&text.prompt, &value, &error).into())
+    }
+
+    fn item(&self, prompt: &str, items: &[&str], value: &toml::Value) -> Result<usize> {
+        value
+            .as_str()
+            .and_then(|answer| items.iter().position(|item| *item == answer))
+            .ok_or_else(|| {
+                let error = format!("expected one of {}", items.join(", "));
+                self.invalid(prompt, value, &error).into()
+            })
+    }
+}
+
+impl Prompter for Script {
+    fn input(&mut self, text: Text) -> Result<String> {
+        self.text(text)
+    }
+
+    fn password(&mut self, text: Text) -> Result<String> {
+        self.text(text)
+    }
+
+    fn confirm(&mut self, prompt: &str, _default: Option<bool>) -> Result<bool> {
+        match self.next(prompt)? {
+            toml::Value::Boolean(answer) => Ok(answer),
+            value => Err(self
+                .invalid(prompt, &value, "expected true or false")
+                .into()),
+        }
+    }
+
+    fn select(&mut self, prompt: &str, items: &[&str], _default: Option<usize>) -> Result<usize> {
+        let value = self.next(prompt)?;
+        self.item(prompt, items, &value)
+    }
+
+    fn multi_select(
+        &mut self,
+        prompt: &str,
+        items: &[&str],
+        _defaults: &[bool],
+    ) -> Result<Vec<usize>> {
+        match self.next(prompt)? {
+            toml::Value::Array(answers) => answers
+                .iter()
+                .map(|answer| self.item(prompt, items, answer))
+                .collect(),
+            value => Err(self.invalid(prompt, &value, "expected a list").into()),
+        }
+    }
+}
+
+/// Asks another prompter and writes every answer to a session file.
+pub struct Recorder {
+    inner: Box<dyn Prompter>,
+    path: PathBuf,
+    session: Session,
+}
+
+impl Recorder {
+    pub fn new(inner: Box<dyn Prompter>, path: impl Into<PathBuf>) -> Recorder {
+        Recorder {
+            inner,
+            path: path.into(),
+            session: Session::default(),
+        }
+    }
+
+    // saved after every answer, so an interrupted session can be replayed
+    // up to where it stopped
+    fn record(&mut self, prompt: &str, answer: Option<toml::Value>) -> Result<()> {
+        self.session.answers.push(Answer {
+            prompt: prompt.to_string(),
+            answer,
+        });
+        std::fs::write(&self.path, toml::to_string(&self.session)?).map_err(|error| {
+            Error::Prompt(format!(
+                "could not write session file `{}`: {}",
+                self.path.display(),
+                error
+            ))
+        })?;
+        Ok(())
+    }
+}
+
+impl Prompter for Recorder {
+    fn input(&mut self, text: Text) -> Result<String> {
+        let prompt = text.prompt.clone();
+        let answer = self.inner.input(text)?;
+        self.record(&prompt, Some(answer.clone().into()))?;
+        Ok(answer)
+    }
+
+    fn password(&mut self, text: Text) -> Result<String> {
+        let prompt = text.prompt.clone();
+        let answer = self.inner.password(text)?;
+        let recorded = match secrets::is_reference(&answer) || answer.is_empty() {
+            true => Some(answer.clone().into()),
+            false => None,
+        };
+        self.record(&prompt, recorded)?;
+        Ok(answer)
+    }
+
+    fn confirm(&mut self, prompt: &str, default: Option<bool>) -> Result<bool> {
+        let answer = self.inner.confirm(prompt, default)?;
+        self.record(prompt, Some(answer.into()))?;
+        Ok(answer)
+    }
+
+    fn select(&mut self, prompt: &str, items: &[&str], default: Option<usize>) -> Result<usize> {
+        let answer = self.inner.select(prompt, items, default)?;
+        self.record(prompt, Some(items[answer].into()))?;
+        Ok(answer)
+    }
+
+    fn multi_select(
+        &mut self,
+        prompt: &str,
+        items: &[&str],
+        defaults: &[bool],
+    ) -> Result<Vec<usize>> {
+        let answer = self.inner.multi_select(prompt, items, defaults)?;
+        let chosen: Vec<toml::Value> = answer.iter().map(|index| items[*index].into()).collect();
+        self.record(prompt, Some(chosen.into()))?;
+        Ok(answer)
+    }
+}
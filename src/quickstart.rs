@@ -0,0 +1,177 @@
+//! Sample data and config for `mycelial quickstart`.
+
+use crate::config::{Config as Configuration, Pipeline, Section};
+use crate::connector::Kind;
+use rusqlite::Connection;
+use uuid::Uuid;
+
+pub(crate) const DATABASE: &str = "quickstart.db";
+pub(crate) const REPLICA: &str = "quickstart_replica.db";
+pub(crate) const PIPELINE: &str = "quickstart";
+pub(crate) const ENDPOINT: &str = "http://localhost:7777";
+const ROWS: usize = 100;
+
+const CUSTOMERS: [(&str, &str); 8] = [
+    ("Ada Lovelace", "ada@example.com"),
+    ("Grace Hopper", "grace@example.com"),
+    ("Alan Turing", "alan@example.com"),
+    ("Katherine Johnson", "katherine@example.com"),
+    ("Edsger Dijkstra", "edsger@example.com"),
+    ("Barbara Liskov", "barbara@example.com"),
+    ("Donald Knuth", "donald@example.com"),
+    ("Margaret Hamilton", "margaret@example.com"),
+];
+
+const PRODUCTS: [(&str, f64); 6] = [
+    ("Espresso Beans 1kg", 24.5),
+    ("Pour Over Kettle", 59.0),
+    ("Burr Grinder", 129.99),
+    ("Ceramic Mug", 12.0),
+    ("Paper Filters (100)", 6.75),
+    ("Cold Brew Jar", 34.9),
+];
+
+const STATUSES: [&str; 4] = ["pending", "paid", "shipped", "delivered"];
+
+/// Entries of the quickstart config: a SQLite source replicated to a SQLite
+/// destination, or hello world connectors.
+pub(crate) fn sections(hello_world: bool) -> [(Kind, Section); 2] {
+    let section = |section_type: &str, display_name: &str, fields: toml::Table| Section {
+        section_type: section_type.to_string(),
+        display_name: display_name.to_string(),
+        fields,
+    };
+    if hello_world {
+        let mut fields = toml::Table::new();
+        fields.insert("interval_milis".to_string(), 5000.into());
+        fields.insert("message".to_string(), "Hello from quickstart!".into());
+        return [
+            (
+                Kind::Source,
+                section("hello_world", "Hello World Source", fields),
+            ),
+            (
+                Kind::Destination,
+                section("hello_world", "Hello World Destination", toml::Table::new()),
+            ),
+        ];
+    }
+    let mut source = toml::Table::new();
+    source.insert("origin".to_string(), "orders".into());
+    source.insert("path".to_string(), DATABASE.into());
+    source.insert("query".to_string(), "select * from orders".into());
+    let mut destination = toml::Table::new();
+    destination.insert("path".to_string(), REPLICA.into());
+    destination.insert("truncate".to_string(), false.into());
+    [
+        (
+            Kind::Source,
+            section("sqlite_connector", "Sample Orders", source),
+        ),
+        (
+            Kind::Destination,
+            section("sqlite_connector", "Sample Replica", destination),
+        ),
+    ]
+}
+
+/// Builds the quickstart config, authenticating with `token`.
+pub(crate) fn config(token: &str, hello_world: bool) -> Configuration {
+    let mut config = Configuration::new();
+    config.set_node(
+        "Quickstart Daemon".to_string(),
+        format!("quickstart-{}", Uuid::new_v4()),
+        "daemon.db".to_string(),
+        token.to_string(),
+    );
+    config.set_server(ENDPOINT.to_string());
+    let mut names = Vec::new();
+    for (kind, section) in sections(hello_world) {
+        names.push(section.display_name.clone());
+        config.add_section(kind, section);
+    }
+    config.add_pipeline(Pipeline {
+        name: PIPELINE.to_string(),
+        source: names[0].clone(),
+        destinations: vec![names[1].clone()],
+        enabled: Some(true),
+    });
+    config
+}
+
+// xorshift, so every quickstart starts from the same rows
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+}
+
+/// Creates the `orders` table of the sample database, leaving a table that
+/// already has rows as it is. Returns the number of rows.
+pub(crate) fn sample_database(path: &str) -> Result<usize, String> {
+    let error = |error: rusqlite::Error| format!("can't write `{}`: {}", path, error);
+    let mut connection = Connection::open(path).map_err(error)?;
+    connection
+        .execute_batch(
+            "create table if not exists orders (
+                id integer primary key,
+                customer text not null,
+                email text not null,
+                product text not null,
+                quantity integer not null,
+                unit_price real not null,
+                status text not null,
+                ordered_at text not null
+            )",
+        )
+        .map_err(error)?;
+    let existing: usize = connection
+        .query_row("select count(*) from orders", [], |row| row.get(0))
+        .map_err(error)?;
+    if existing > 0 {
+        return Ok(existing);
+    }
+    let transaction = connection.transaction().map_err(error)?;
+    {
+        let mut insert = transaction
+            .prepare(
+                "insert into orders \
+                 (id, customer, email, product, quantity, unit_price, status, ordered_at) \
+                 values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )
+            .map_err(error)?;
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for id in 1..=ROWS {
+            let (customer, email) = CUSTOMERS[rng.next(CUSTOMERS.len())];
+            let (product, unit_price) = PRODUCTS[rng.next(PRODUCTS.len())];
+            // older orders are further along
+            let status = STATUSES[(STATUSES.len() - 1).min(rng.next(2) + (ROWS - id) / 25)];
+            let ordered_at = format!(
+                "2024-{:02}-{:02} {:02}:{:02}:00",
+                1 + (id - 1) * 12 / ROWS,
+                1 + rng.next(28),
+                8 + rng.next(12),
+                rng.next(60)
+            );
+            insert
+                .execute(rusqlite::params![
+                    id,
+                    customer,
+                    email,
+                    product,
+                    1 + rng.next(4),
+                    unit_price,
+                    status,
+                    ordered_at
+                ])
+                .map_err(error)?;
+        }
+    }
+    transaction.commit().map_err(error)?;
+    Ok(ROWS)
+}
@@ -0,0 +1,313 @@
+//! Which release of the daemon and control plane is downloaded.
+//!
+//! Binaries come from the latest release, unless a version is pinned with
+//! `init --version` or `update --version`. The pin, and the versions last
+//! downloaded, are kept in `~/.mycelial/state.toml`:
+//!
+//! ```toml
+//! pinned = "v0.4.2"
+//!
+//! [installed]
+//! daemon = "v0.4.2"
+//! control_plane = "v0.4.2"
+//! ```
+
+use crate::{ask_binary, mycelial_dir, output, Error, Prompter, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const RELEASES: &str = "https://github.com/mycelial/mycelial/releases";
+const RELEASES_ENV: &str = "MYCELIAL_RELEASES";
+
+/// A release tag, `vMAJOR.MINOR.PATCH` with an optional `-PRE` suffix.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Version {
+    major: u64,
+    minor: u64,
+    patch: u64,
+    pre: Option<String>,
+}
+
+impl Version {
+    /// Whether `to` has another major version, or another minor version
+    /// before 1.0, which may not read the same config or database.
+    fn is_major_jump(&self, to: &Version) -> bool {
+        match (self.major, to.major) {
+            (0, 0) => self.minor != to.minor,
+            (from, to) => from != to,
+        }
+    }
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(tag: &str) -> std::result::Result<Version, String> {
+        let error = || {
+            format!(
+                "`{}` is not a release version, expected vX.Y.Z or `latest`",
+                tag
+            )
+        };
+        let version = tag.strip_prefix('v').unwrap_or(tag);
+        let (numbers, pre) = match version.split_once('-') {
+            Some((numbers, pre)) if !pre.is_empty() => (numbers, Some(pre.to_string())),
+            Some(_) => return Err(error()),
+            None => (version, None),
+        };
+        let numbers = numbers
+            .split('.')
+            .map(str::parse::<u64>)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|_| error())?;
+        match numbers[..] {
+            [major, minor, patch] => Ok(Version {
+                major,
+                minor,
+                patch,
+                pre,
+            }),
+            _ => Err(error()),
+        }
+    }
+}
+
+impl TryFrom<String> for Version {
+    type Error = String;
+
+    fn try_from(tag: String) -> std::result::Result<Version, String> {
+        tag.parse()
+    }
+}
+
+impl From<Version> for String {
+    fn from(version: Version) -> String {
+        version.to_string()
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}.{}.{}", self.major, self.minor, self.patch)?;
+        match &self.pre {
+            Some(pre) => write!(f, "-{}", pre),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The release asked for with `--version`: a tag, which is pinned, or
+/// `latest`, which removes the pin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Release {
+    Latest,
+    Tag(Version),
+}
+
+impl FromStr for Release {
+    type Err = String;
+
+    fn from_str(release: &str) -> std::result::Result<Release, String> {
+        match release {
+            "latest" => Ok(Release::Latest),
+            tag => tag.parse().map(Release::Tag),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pinned: Option<Version>,
+    #[serde(default)]
+    installed: Installed,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Installed {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    daemon: Option<Version>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    control_plane: Option<Version>,
+}
+
+fn state_path() -> PathBuf {
+    mycelial_dir().join("state.toml")
+}
+
+impl State {
+    fn load() -> Result<State> {
+        let path = state_path();
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(State::default())
+            }
+            Err(error) => {
+                return Err(Error::Config(format!(
+                    "could not read `{}`: {}",
+                    path.display(),
+                    error
+                ))
+                .into())
+            }
+        };
+        toml::from_str(&contents).map_err(|error| {
+            Error::Config(format!("invalid `{}`: {}", path.display(), error)).into()
+        })
+    }
+
+    fn save(&self) -> Result<()> {
+        fs::create_dir_all(mycelial_dir())?;
+        fs::write(state_path(), toml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+// the releases page, or the mirror set with MYCELIAL_RELEASES
+fn releases_url() -> String {
+    match std::env::var(RELEASES_ENV) {
+        Ok(url) => url.trim_end_matches('/').to_string(),
+        Err(_) => RELEASES.to_string(),
+    }
+}
+
+/// Url of a release asset, from the latest release when the version isn't
+/// known.
+pub(crate) fn url(version: Option<&Version>, asset: &str) -> String {
+    match version {
+        Some(version) => format!("{}/download/{}/{}", releases_url(), version, asset),
+        None => format!("{}/latest/download/{}", releases_url(), asset),
+    }
+}
+
+/// The version to download: the one asked for, else the pinned one, else
+/// the latest, which is `None` when its tag can't be told.
+pub(crate) async fn resolve(release: Option<&Release>) -> Result<Option<Version>> {
+    match release {
+        Some(Release::Tag(version)) => Ok(Some(version.clone())),
+        Some(Release::Latest) => latest().await,
+        None => match State::load()?.pinned {
+            Some(version) => Ok(Some(version)),
+            None => latest().await,
+        },
+    }
+}
+
+// the latest release redirects to the page of its tag
+async fn latest() -> Result<Option<Version>> {
+    let url = format!("{}/latest", releases_url());
+    let response = reqwest::Client::new()
+        .head(&url)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|error| {
+            Error::Download(format!(
+                "could not find the latest release at {}: {}",
+                url, error
+            ))
+        })?;
+    Ok(response
+        .url()
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .and_then(|tag| tag.parse().ok()))
+}
+
+/// Asks before replacing binaries with another major version, which is
+/// refused unless confirmed or `--yes` is passed. So is replacing them when
+/// either version can't be told: the installed one is recorded when it was
+/// downloaded, else asked to the binary with `--version`.
+pub(crate) fn check_update(
+    p: &mut dyn Prompter,
+    daemon: bool,
+    control_plane: bool,
+    to: Option<&Version>,
+) -> Result<()> {
+    let installed = State::load()?.installed;
+    let binaries = [
+        (daemon, "daemon", "myceliald", installed.daemon),
+        (
+            control_plane,
+            "control plane",
+            "server",
+            installed.control_plane,
+        ),
+    ];
+    for (updated, name, binary, recorded) in binaries {
+        let from = match recorded {
+            _ if !updated => continue,
+            Some(version) => Some(version),
+            None if Path::new(binary).exists() => binary_version(binary),
+            // nothing to replace
+            None => continue,
+        };
+        let update = match (from, to) {
+            (Some(from), Some(to)) if from.is_major_jump(to) => {
+                format!("the {} from {} to {}, a new major version", name, from, to)
+            }
+            (Some(_), Some(_)) => continue,
+            (None, Some(to)) => format!("the {} from an unknown version to {}", name, to),
+            (Some(from), None) => format!(
+                "the {} from {} to the latest release, whose version is unknown",
+                name, from
+            ),
+            (None, None) => format!(
+                "the {} from an unknown version to the latest release, whose version is unknown",
+                name
+            ),
+        };
+        if !p.confirm(&format!("Update {}?", update), Some(false))? {
+            return Err(Error::Declined(format!(
+                "not updating {} without confirmation (pass --yes to confirm)",
+                update
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+// the version printed by `./binary --version`, like `myceliald 0.4.2`
+fn binary_version(binary: &str) -> Option<Version> {
+    let output = ask_binary(&Path::new(".").join(binary), "--version").ok()??;
+    output.split_whitespace().find_map(|word| word.parse().ok())
+}
+
+/// Pins or unpins the release asked for, and records the version of the
+/// binaries downloaded.
+pub(crate) fn record(
+    release: Option<&Release>,
+    downloaded: Option<&Version>,
+    daemon: bool,
+    control_plane: bool,
+) -> Result<()> {
+    if release.is_none() && !daemon && !control_plane {
+        return Ok(());
+    }
+    let mut state = State::load()?;
+    match release {
+        Some(Release::Tag(version)) => {
+            output::say(format!("pinned release {}", version));
+            state.pinned = Some(version.clone());
+        }
+        Some(Release::Latest) if state.pinned.is_some() => {
+            output::say("unpinned release, downloading the latest from now on");
+            state.pinned = None;
+        }
+        _ => {}
+    }
+    if daemon {
+        state.installed.daemon = downloaded.cloned();
+    }
+    if control_plane {
+        state.installed.control_plane = downloaded.cloned();
+    }
+    output::set("pinned", &state.pinned);
+    state.save()
+}
@@ -0,0 +1,291 @@
+//! Secret references in config values.
+//!
+//! Any string in the config may contain `${env:NAME}`, `${file:PATH}` or
+//! `${vault:NAME}`, which are replaced with the value of the environment
+//! variable, the contents of the file or the vault entry when the daemon is
+//! started. References inside a connection url are percent-encoded when
+//! resolved.
+
+use crate::connection;
+use crate::prompt::Prompter;
+use crate::vault::Vault;
+use crate::{Error, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SCHEMES: &[&str] = &["env", "file", "vault"];
+
+struct Reference<'a> {
+    // byte range of `${...}` in the containing string
+    start: usize,
+    end: usize,
+    scheme: &'a str,
+    argument: &'a str,
+}
+
+// finds references with a known scheme, other `${...}` text is left alone
+fn references(value: &str) -> Vec<std::result::Result<Reference<'_>, String>> {
+    let mut found = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = value[offset..].find("${").map(|i| i + offset) {
+        let rest = &value[start + 2..];
+        let scheme = match rest.split_once(':') {
+            Some((scheme, _)) if SCHEMES.contains(&scheme) => scheme,
+            _ => {
+                offset = start + 2;
+                continue;
+            }
+        };
+        match rest.find('}') {
+            Some(close) => {
+                let argument = &rest[scheme.len() + 1..close];
+                let end = start + 2 + close + 1;
+                if argument.is_empty() {
+                    found.push(Err(format!(
+                        "`{}` is missing a {}",
+                        &value[start..end],
+                        if scheme == "env" {
+                            "variable name"
+                        } else {
+                            "path"
+                        }
+                    )));
+                } else {
+                    found.push(Ok(Reference {
+                        start,
+                        end,
+                        scheme,
+                        argument,
+                    }));
+                }
+                offset = end;
+            }
+            None => {
+                found.push(Err(format!(
+                    "unterminated secret reference `{}`",
+                    &value[start..]
+                )));
+                break;
+            }
+        }
+    }
+    found
+}
+
+/// Returns true if `value` contains at least one secret reference.
+pub(crate) fn has_reference(value: &str) -> bool {
+    !references(value).is_empty()
+}
+
+/// Returns true if `value` is nothing but a single secret reference.
+pub(crate) fn is_reference(value: &str) -> bool {
+    match references(value).as_slice() {
+        [Ok(reference)] => reference.start == 0 && reference.end == value.len(),
+        _ => false,
+    }
+}
+
+/// Checks the syntax of every secret reference in `value`.
+pub(crate) fn check_references(value: &str) -> std::result::Result<(), String> {
+    match references(value).into_iter().find_map(|r| r.err()) {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// Replaces secret references with a placeholder, for checks that only
+/// care about the shape of a value.
+pub(crate) fn mask_references(value: &str) -> String {
+    let mut masked = String::with_capacity(value.len());
+    let mut last = 0;
+    for reference in references(value).into_iter().flatten() {
+        masked.push_str(&value[last..reference.start]);
+        masked.push_str("secret");
+        last = reference.end;
+    }
+    masked.push_str(&value[last..]);
+    masked
+}
+
+/// Part of a config value, as split by [`segments`].
+pub(crate) enum Segment<'a> {
+    Literal(&'a str),
+    Reference(&'a str),
+}
+
+/// Splits `value` into literal text and well formed secret references.
+pub(crate) fn segments(value: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut last = 0;
+    for reference in references(value).into_iter().flatten() {
+        if reference.start > last {
+            segments.push(Segment::Literal(&value[last..reference.start]));
+        }
+        segments.push(Segment::Reference(&value[reference.start..reference.end]));
+        last = reference.end;
+    }
+    if last < value.len() {
+        segments.push(Segment::Literal(&value[last..]));
+    }
+    segments
+}
+
+struct Resolver<'a> {
+    // asks for the vault passphrase
+    prompter: &'a mut dyn Prompter,
+    key_file: Option<&'a str>,
+    // opened on first use, so configs without vault references never ask for a passphrase
+    vault: Option<Vault>,
+}
+
+impl Resolver<'_> {
+    fn lookup(&mut self, scheme: &str, argument: &str) -> Result<String> {
+        match scheme {
+            "env" => std::env::var(argument)
+                .map_err(|_| format!("environment variable `{}` is not set", argument).into()),
+            "file" => match fs::read_to_string(argument) {
+                Ok(contents) => Ok(contents.trim_end_matches(['\n', '\r']).to_string()),
+                Err(error) => {
+                    Err(format!("could not read secret file `{}`: {}", argument, error).into())
+                }
+            },
+            "vault" => {
+                if self.vault.is_none() {
+                    self.vault = Some(Vault::open(self.prompter, self.key_file)?);
+                }
+                match self.vault.as_ref().and_then(|vault| vault.get(argument)) {
+                    Some(value) => Ok(value.to_string()),
+                    None => Err(Error::Secret(format!(
+                        "no secret named `{}` in the vault",
+                        argument
+                    ))
+                    .into()),
+                }
+            }
+            _ => unreachable!("unknown secret scheme"),
+        }
+    }
+
+    // replaces every secret reference in `value` with the secret it points at,
+    // percent-encoded when it is only part of a url
+    fn resolve_str(&mut self, value: &str, url: bool) -> Result<String> {
+        let encode = url && !is_reference(value);
+        let mut resolved = String::with_capacity(value.len());
+        let mut last = 0;
+        for reference in references(value) {
+            let reference = reference?;
+            resolved.push_str(&value[last..reference.start]);
+            let secret = self.lookup(reference.scheme, reference.argument)?;
+            match encode {
+                true => resolved.push_str(&connection::encode_component(&secret)),
+                false => resolved.push_str(&secret),
+            }
+            last = reference.end;
+        }
+        resolved.push_str(&value[last..]);
+        Ok(resolved)
+    }
+
+    // resolves all strings in place, returns true if anything was replaced
+    fn resolve_value(&mut self, value: &mut toml::Value, url: bool) -> Result<bool> {
+        match value {
+            toml::Value::String(string) if has_reference(string) => {
+                *string = self.resolve_str(string, url)?;
+                Ok(true)
+            }
+            toml::Value::Array(array) => {
+                let mut changed = false;
+                for value in array.iter_mut() {
+                    changed |= self.resolve_value(value, false)?;
+                }
+                Ok(changed)
+            }
+            toml::Value::Table(table) => {
+                let mut changed = false;
+                for (key, value) in table.iter_mut() {
+                    changed |= self.resolve_value(value, key == "url")?;
+                }
+                Ok(changed)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+/// Resolves the secret references in a config value, percent-encoding
+/// secrets that are only part of a url.
+pub(crate) fn resolve_value(p: &mut dyn Prompter, value: &str, url: bool) -> Result<String> {
+    let mut resolver = Resolver {
+        prompter: p,
+        key_file: None,
+        vault: None,
+    };
+    resolver.resolve_str(value, url)
+}
+
+/// Returns the config with all secrets resolved, or `None` when it has no
+/// references and can be used as is.
+pub(crate) fn resolve_config(
+    p: &mut dyn Prompter,
+    config_file_name: &str,
+    key_file: Option<&str>,
+) -> Result<Option<String>> {
+    let contents = fs::read_to_string(config_file_name)?;
+    let mut value: toml::Value = toml::from_str(&contents)?;
+    let mut resolver = Resolver {
+        prompter: p,
+        key_file,
+        vault: None,
+    };
+    if !resolver.resolve_value(&mut value, false)? {
+        return Ok(None);
+    }
+    Ok(Some(toml::to_string(&value)?))
+}
+
+/// Creates `dir`, and its parents, with only the current user allowed in.
+pub(crate) fn create_private_dir(dir: &Path) -> Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)?;
+    Ok(())
+}
+
+/// A new directory in the temporary directory that only the current user
+/// can enter, removed with everything in it when dropped.
+pub(crate) struct PrivateDir(PathBuf);
+
+impl PrivateDir {
+    pub(crate) fn create() -> Result<PrivateDir> {
+        let dir = std::env::temp_dir().join(format!("mycelial-{}", uuid::Uuid::new_v4()));
+        create_private_dir(&dir)?;
+        Ok(PrivateDir(dir))
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for PrivateDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Writes `contents` to a file only the current user can read.
+pub(crate) fn write_private(path: &Path, contents: &str) -> Result<()> {
+    // mode only applies to new files
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    std::io::Write::write_all(&mut file, contents.as_bytes())?;
+    Ok(())
+}
@@ -1,28 +1,56 @@
-use dialoguer::{theme::ColorfulTheme, Confirm};
-use mycelial::{create_config, download_binaries, ConfigAction};
+use mycelial::{
+    create_config, download_binaries, ensure, output, resolve_secrets, ConfigAction, Error,
+    Prompter,
+};
+use serde_json::json;
 use service_manager::*;
 use std::ffi::OsString;
 use std::fs;
+use std::os::unix::process::CommandExt;
 use std::path::Path;
-use std::path::PathBuf;
 use std::process::{Command, Stdio};
 
 pub struct Service {}
 
+// the service manager of this host, systemd or launchd
+fn manager() -> Result<Box<dyn ServiceManager>> {
+    <dyn ServiceManager>::native().map_err(|error| {
+        Error::Service(format!("could not detect the service manager: {}", error)).into()
+    })
+}
+
+fn service_error(action: &str, error: std::io::Error) -> Error {
+    Error::Service(format!(
+        "could not {} the daemon service: {}",
+        action, error
+    ))
+}
+
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 const CLIENT_DEST_PATH: &str = "/usr/local/bin/myceliald";
 const CLIENT_CONFIG_PATH: &str = "/etc/mycelial/config.toml";
+// config with secret references resolved, what the daemon reads. The service
+// writes it every time it starts, and it is removed when the service stops.
+#[cfg(target_os = "macos")]
+const CLIENT_RESOLVED_CONFIG_PATH: &str = "/var/run/mycelial/resolved.toml";
+#[cfg(not(target_os = "macos"))]
+const CLIENT_RESOLVED_CONFIG_PATH: &str = "/run/mycelial/resolved.toml";
 const CLIENT_DB_PATH: &str = "/var/lib/mycelial/daemon.db";
 const SERVICE_LABEL: &str = "com.mycelial.daemon";
 impl Service {
     pub fn new() -> Service {
         Service {}
     }
-    pub async fn add_client(&self, config: Option<String>) -> Result<()> {
+    pub async fn add_client(
+        &self,
+        p: &mut dyn Prompter,
+        config: Option<String>,
+        key_file: Option<String>,
+    ) -> Result<()> {
         self.download_client().await?;
-        self.configure_client(config).await?;
-        self.check_client_database()?;
-        self.install_and_start()?;
+        self.configure_client(p, config).await?;
+        self.check_client_database(p)?;
+        self.install_and_start(key_file)?;
         Ok(())
     }
     pub async fn remove_client(&self, purge: bool) -> Result<()> {
@@ -33,7 +61,7 @@ impl Service {
         Ok(())
     }
     async fn download_client(&self) -> Result<()> {
-        download_binaries(true, false).await?;
+        download_binaries(true, false, None).await?;
         let path = Path::new(CLIENT_DEST_PATH);
         if path.exists() {
             fs::remove_file(path)?;
@@ -41,31 +69,29 @@ impl Service {
         fs::rename("myceliald", CLIENT_DEST_PATH)?;
         Ok(())
     }
-    fn check_client_database(&self) -> Result<()> {
+    fn check_client_database(&self, p: &mut dyn Prompter) -> Result<()> {
         if Path::new(CLIENT_DB_PATH).exists() {
-            let theme = ColorfulTheme::default();
-            let confirm = Confirm::with_theme(&theme)
-                .with_prompt("Overwrite existing daemon database?")
-                .default(false)
-                .interact()?;
-            if confirm {
+            // kept unless --yes is passed
+            if p.confirm("Overwrite existing daemon database?", Some(false))? {
                 fs::remove_file(CLIENT_DB_PATH)?;
             }
         }
         Ok(())
     }
 
-    async fn configure_client(&self, config: Option<String>) -> Result<()> {
+    async fn configure_client(&self, p: &mut dyn Prompter, config: Option<String>) -> Result<()> {
         fs::create_dir_all("/var/lib/mycelial")?;
         fs::create_dir_all("/etc/mycelial")?;
         let mut config_action: Option<ConfigAction> = None;
-        if Path::new(CLIENT_CONFIG_PATH).exists() {
-            let theme = ColorfulTheme::default();
-            let confirm = Confirm::with_theme(&theme)
-                .with_prompt("Overwrite existing configuration?")
-                .default(false)
-                .interact()?;
-            if confirm {
+        if config.is_none() {
+            ensure(
+                "configuring the daemon",
+                "pass --config with the config file to install",
+            )?;
+        }
+        // a config passed with --config replaces the existing one anyway
+        if config.is_none() && Path::new(CLIENT_CONFIG_PATH).exists() {
+            if p.confirm("Overwrite existing configuration?", Some(false))? {
                 config_action = Some(ConfigAction::Create);
             } else {
                 config_action = Some(ConfigAction::UseExisting);
@@ -78,6 +104,7 @@ impl Service {
             }
             None => {
                 create_config(
+                    p,
                     CLIENT_CONFIG_PATH.to_string(),
                     database_storage_path,
                     config_action,
@@ -89,52 +116,105 @@ impl Service {
         }
         Ok(())
     }
-    fn install_and_start(&self) -> Result<()> {
+    // only needed while the daemon runs, the service resolves it again when
+    // it starts
+    fn remove_resolved_config(&self) -> Result<()> {
+        if Path::new(CLIENT_RESOLVED_CONFIG_PATH).exists() {
+            fs::remove_file(CLIENT_RESOLVED_CONFIG_PATH)?;
+            output::push("deleted", CLIENT_RESOLVED_CONFIG_PATH);
+            output::say(format!(
+                "daemon resolved configuration deleted {}",
+                CLIENT_RESOLVED_CONFIG_PATH
+            ));
+        }
+        Ok(())
+    }
+    // the service runs `mycelial service run --daemon` rather than the daemon
+    // itself, so the secrets are resolved on every start, after a reboot too,
+    // and changes to the config are picked up without installing it again
+    fn install_and_start(&self, key_file: Option<String>) -> Result<()> {
         let label: ServiceLabel = SERVICE_LABEL.parse()?;
-        let manager = <dyn ServiceManager>::native().expect("Failed to detect management platform");
+        let manager = manager()?;
+        let program = std::env::current_exe().map_err(|error| {
+            Error::Service(format!("could not find the mycelial binary: {}", error))
+        })?;
+        let mut args: Vec<OsString> = ["service", "run", "--daemon", "--non-interactive"]
+            .into_iter()
+            .map(OsString::from)
+            .collect();
+        if let Some(key_file) = key_file {
+            let key_file = fs::canonicalize(&key_file).map_err(|error| {
+                Error::Service(format!("could not read key file `{}`: {}", key_file, error))
+            })?;
+            args.push(OsString::from("--key-file"));
+            args.push(key_file.into_os_string());
+        }
         manager
             .install(ServiceInstallCtx {
                 label: label.clone(),
-                program: PathBuf::from(CLIENT_DEST_PATH),
-                args: vec![OsString::from(format!("--config={}", CLIENT_CONFIG_PATH))],
+                program: program.clone(),
+                args,
                 contents: None, // Optional String for system-specific service content.
                 username: None, // Optional String for alternative user to run service.
                 working_directory: None, // Optional String for the working directory for the service process.
                 environment: None, // Optional list of environment variables to supply the service process.
             })
-            .expect("Failed to install");
+            .map_err(|error| service_error("install", error))?;
         manager
             .start(ServiceStartCtx {
                 label: label.clone(),
             })
-            .expect("Failed to start");
-        println!("Mycelial daemon installed and started");
+            .map_err(|error| service_error("start", error))?;
+        output::set(
+            "installed",
+            json!({
+                "label": SERVICE_LABEL,
+                "program": program,
+                "daemon": CLIENT_DEST_PATH,
+                "config": CLIENT_CONFIG_PATH,
+            }),
+        );
+        output::say("Mycelial daemon installed and started");
         Ok(())
     }
     fn uninstall_client(&self) -> Result<()> {
         let label: ServiceLabel = SERVICE_LABEL.parse()?;
-        let manager = <dyn ServiceManager>::native().expect("Failed to detect management platform");
+        let manager = manager()?;
         match manager.stop(ServiceStopCtx {
             label: label.clone(),
         }) {
-            Ok(_) => println!("Mycelial daemon stopped"),
-            Err(_) => println!("Mycelial daemon not running"),
+            Ok(_) => {
+                output::set("was_running", true);
+                output::say("Mycelial daemon stopped");
+            }
+            Err(_) => {
+                output::set("was_running", false);
+                output::say("Mycelial daemon not running");
+            }
         }
         manager
             .uninstall(ServiceUninstallCtx {
                 label: label.clone(),
             })
-            .expect("Failed to uninstall");
-        println!("daemon service removed");
+            .map_err(|error| service_error("uninstall", error))?;
+        self.remove_resolved_config()?;
+        output::set("removed", SERVICE_LABEL);
+        output::say("daemon service removed");
         Ok(())
     }
     fn purge_client(&self) -> Result<()> {
         fs::remove_file(CLIENT_CONFIG_PATH)?;
-        println!("daemon configuration deleted {}", CLIENT_CONFIG_PATH);
+        output::push("deleted", CLIENT_CONFIG_PATH);
+        output::say(format!(
+            "daemon configuration deleted {}",
+            CLIENT_CONFIG_PATH
+        ));
         fs::remove_file(CLIENT_DEST_PATH)?;
-        println!("daemon binary deleted {}", CLIENT_DEST_PATH);
+        output::push("deleted", CLIENT_DEST_PATH);
+        output::say(format!("daemon binary deleted {}", CLIENT_DEST_PATH));
         fs::remove_file(CLIENT_DB_PATH)?;
-        println!("daemon database deleted {}", CLIENT_DB_PATH);
+        output::push("deleted", CLIENT_DB_PATH);
+        output::say(format!("daemon database deleted {}", CLIENT_DB_PATH));
         Ok(())
     }
     pub fn status_client(&self) -> Result<()> {
@@ -153,12 +233,30 @@ impl Service {
             .spawn()?
             .wait_with_output()?;
         let stdout = String::from_utf8_lossy(&output.stdout);
+        if output::json() {
+            // PID, last exit status and label, tab separated
+            let service = stdout
+                .lines()
+                .map(|line| line.split('\t').collect::<Vec<_>>())
+                .find(|fields| fields.len() == 3 && fields[2] == SERVICE_LABEL);
+            let status = match service {
+                Some(fields) => json!({
+                    "label": SERVICE_LABEL,
+                    "installed": true,
+                    "pid": fields[0].parse::<u32>().ok(),
+                    "last_exit_status": fields[1].parse::<i32>().ok(),
+                }),
+                None => json!({ "label": SERVICE_LABEL, "installed": false }),
+            };
+            output::set("service", status);
+            return Ok(());
+        }
         for line in stdout.lines() {
             if is_first_line {
-                println!("{}", line);
+                output::say(line);
                 is_first_line = false;
             } else if line.contains(SERVICE_LABEL) {
-                println!("{}", line);
+                output::say(line);
             }
         }
         Ok(())
@@ -166,6 +264,44 @@ impl Service {
     fn status_client_systemd(&self) -> Result<()> {
         let label: ServiceLabel = SERVICE_LABEL.parse()?;
         let script_name = label.to_script_name();
+        if output::json() {
+            let output = Command::new("systemctl")
+                .arg("show")
+                .arg(&script_name)
+                .arg("--property=LoadState,ActiveState,SubState,MainPID")
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .and_then(|child| child.wait_with_output())
+                .map_err(|error| service_error("query", error))?;
+            if !output.status.success() {
+                return Err(Error::Service(format!(
+                    "could not query the daemon service: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ))
+                .into());
+            }
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let property = |name: &str| {
+                stdout
+                    .lines()
+                    .find_map(|line| line.strip_prefix(&format!("{}=", name)))
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            output::set(
+                "service",
+                json!({
+                    "label": SERVICE_LABEL,
+                    "load_state": property("LoadState"),
+                    "active_state": property("ActiveState"),
+                    "sub_state": property("SubState"),
+                    // systemd reports 0 when there is no main process
+                    "pid": property("MainPID").parse::<u32>().ok().filter(|pid| *pid != 0),
+                }),
+            );
+            return Ok(());
+        }
         let output = Command::new("systemctl")
             .arg("status")
             .arg(script_name)
@@ -174,16 +310,26 @@ impl Service {
             .wait_with_output()?;
         let stdout = String::from_utf8_lossy(&output.stdout);
         for line in stdout.lines() {
-            println!("{}", line);
+            output::say(line);
         }
         Ok(())
     }
+    /// What the installed service runs: resolves the secrets of the daemon's
+    /// config, then replaces this process with the daemon.
+    pub fn run_client(&self, p: &mut dyn Prompter, key_file: Option<&str>) -> Result<()> {
+        resolve_secrets(p, CLIENT_CONFIG_PATH, CLIENT_RESOLVED_CONFIG_PATH, key_file)?;
+        let error = Command::new(CLIENT_DEST_PATH)
+            .arg(format!("--config={}", CLIENT_RESOLVED_CONFIG_PATH))
+            .exec();
+        Err(Error::Process(format!("could not run the daemon: {}", error)).into())
+    }
     pub fn start_client(&self) -> Result<()> {
         match std::env::consts::OS {
             "macos" => self.start_client_launchctrl()?,
             "linux" => self.start_client_systemd()?,
             _ => {}
         }
+        output::set("started", SERVICE_LABEL);
         Ok(())
     }
     // service_manager crate doesn't support launchctrl properly
@@ -194,18 +340,19 @@ impl Service {
             .arg("load")
             .arg(plist_path)
             .stdout(Stdio::piped())
-            .spawn()?
-            .wait()?;
+            .spawn()
+            .and_then(|mut child| child.wait())
+            .map_err(|error| service_error("load", error))?;
         Ok(())
     }
     pub fn start_client_systemd(&self) -> Result<()> {
         let label: ServiceLabel = SERVICE_LABEL.parse()?;
-        let manager = <dyn ServiceManager>::native().expect("Failed to detect management platform");
+        let manager = manager()?;
         manager
             .start(ServiceStartCtx {
                 label: label.clone(),
             })
-            .expect("Failed to start");
+            .map_err(|error| service_error("start", error))?;
         Ok(())
     }
     pub fn stop_client(&self) -> Result<()> {
@@ -214,6 +361,8 @@ impl Service {
             "linux" => self.stop_client_systemd()?,
             _ => {}
         }
+        self.remove_resolved_config()?;
+        output::set("stopped", SERVICE_LABEL);
         Ok(())
     }
     // service_manager crate doesn't support launchctrl properly
@@ -224,18 +373,19 @@ impl Service {
             .arg("unload")
             .arg(plist_path)
             .stdout(Stdio::piped())
-            .spawn()?
-            .wait()?;
+            .spawn()
+            .and_then(|mut child| child.wait())
+            .map_err(|error| service_error("unload", error))?;
         Ok(())
     }
     fn stop_client_systemd(&self) -> Result<()> {
         let label: ServiceLabel = SERVICE_LABEL.parse()?;
-        let manager = <dyn ServiceManager>::native().expect("Failed to detect management platform");
+        let manager = manager()?;
         manager
             .stop(ServiceStopCtx {
                 label: label.clone(),
             })
-            .expect("Failed to stop");
+            .map_err(|error| service_error("stop", error))?;
         Ok(())
     }
     pub fn restart_client(&self) -> Result<()> {
@@ -250,6 +400,7 @@ impl Service {
             }
             _ => {}
         }
+        output::set("restarted", SERVICE_LABEL);
         Ok(())
     }
 }
@@ -0,0 +1,484 @@
+use crate::connector::{self, Conflict, Connector, FieldType, Kind};
+use crate::{connection, secrets};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops::Range;
+use toml_edit::{ImDocument, Item, Key, TableLike, Value};
+
+#[derive(Serialize)]
+pub(crate) struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum FieldKind {
+    String,
+    Bool,
+    Integer,
+    StringArray,
+    // may be left out
+    OptionalString,
+    OptionalBool,
+}
+
+impl FieldKind {
+    fn name(&self) -> &'static str {
+        match self {
+            FieldKind::String | FieldKind::OptionalString => "a string",
+            FieldKind::Bool | FieldKind::OptionalBool => "a boolean",
+            FieldKind::Integer => "an integer",
+            FieldKind::StringArray => "an array of strings",
+        }
+    }
+
+    // only string fields may hold secret references
+    fn matches_reference(&self) -> bool {
+        matches!(self, FieldKind::String | FieldKind::OptionalString)
+    }
+
+    fn is_optional(&self) -> bool {
+        matches!(self, FieldKind::OptionalString | FieldKind::OptionalBool)
+    }
+
+    fn matches(&self, value: &Value) -> bool {
+        match self {
+            FieldKind::String | FieldKind::OptionalString => value.is_str(),
+            FieldKind::Bool | FieldKind::OptionalBool => value.is_bool(),
+            FieldKind::Integer => value.is_integer(),
+            FieldKind::StringArray => value
+                .as_array()
+                .map(|array| array.iter().all(|value| value.is_str()))
+                .unwrap_or(false),
+        }
+    }
+}
+
+type Fields<'a> = &'a [(&'a str, FieldKind)];
+
+const NODE_FIELDS: Fields = &[
+    ("display_name", FieldKind::String),
+    ("unique_id", FieldKind::String),
+    ("storage_path", FieldKind::String),
+    ("auth_token", FieldKind::String),
+];
+
+const SERVER_FIELDS: Fields = &[("endpoint", FieldKind::String)];
+
+// keep in sync with `Pipeline` in config.rs
+const PIPELINE_FIELDS: Fields = &[
+    ("name", FieldKind::String),
+    ("source", FieldKind::String),
+    ("destinations", FieldKind::StringArray),
+    ("enabled", FieldKind::OptionalBool),
+];
+
+/// Checks a config file and returns every problem found, in document order.
+pub(crate) fn validate(contents: &str) -> Vec<Diagnostic> {
+    let mut validator = Validator {
+        contents,
+        diagnostics: Vec::new(),
+        display_names: HashMap::new(),
+        source_names: HashSet::new(),
+        destination_names: HashSet::new(),
+    };
+    match ImDocument::parse(contents) {
+        Ok(document) => validator.document(document.as_table()),
+        Err(error) => {
+            let message = error.message().trim().replace('\n', "; ");
+            validator.report(error.span(), format!("invalid TOML: {}", message));
+        }
+    }
+    validator
+        .diagnostics
+        .sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+    validator.diagnostics
+}
+
+/// Checks that `url` is a well formed connection url for one of `schemes`.
+pub(crate) fn check_connection_url(url: &str, schemes: &[&str]) -> Result<(), String> {
+    let parsed = url::Url::parse(url).map_err(|error| format!("malformed url: {}", error))?;
+    if !schemes.contains(&parsed.scheme()) {
+        return Err(format!(
+            "url scheme must be `{}`, found `{}`",
+            schemes.join("` or `"),
+            parsed.scheme()
+        ));
+    }
+    match parsed.host_str() {
+        Some(host) if !host.is_empty() => {}
+        _ => return Err("url is missing a host".into()),
+    }
+    if parsed.path().trim_start_matches('/').is_empty() {
+        return Err("url is missing a database name".into());
+    }
+    if let Some(tls) = connection::tls_params(parsed.scheme()) {
+        for (key, value) in parsed.query_pairs() {
+            if key == tls.mode {
+                tls.check_mode(&value)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Validator<'a> {
+    contents: &'a str,
+    diagnostics: Vec<Diagnostic>,
+    // kind and display name -> line of first definition
+    display_names: HashMap<(Kind, String), usize>,
+    // names pipelines may refer to
+    source_names: HashSet<String>,
+    destination_names: HashSet<String>,
+}
+
+impl<'a> Validator<'a> {
+    fn document(&mut self, root: &toml_edit::Table) {
+        for (section, fields) in [("node", NODE_FIELDS), ("server", SERVER_FIELDS)] {
+            match root.get(section) {
+                None => self.report(None, format!("missing [{}] section", section)),
+                Some(item) => match item.as_table_like() {
+                    Some(table) => {
+                        self.fields(table, item.span(), fields, &format!("[{}]", section))
+                    }
+                    None => self.report(item.span(), format!("`{}` must be a table", section)),
+                },
+            }
+        }
+        self.sections(root, "sources", Kind::Source);
+        self.sections(root, "destinations", Kind::Destination);
+        self.pipelines(root);
+    }
+
+    fn tables<'t>(
+        &mut self,
+        item: &'t Item,
+        key: &str,
+    ) -> Vec<(&'t dyn TableLike, Option<Range<usize>>)> {
+        match item {
+            Item::ArrayOfTables(array) => array
+                .iter()
+                .map(|table| (table as &dyn TableLike, table.span()))
+                .collect(),
+            Item::Value(Value::Array(array)) => {
+                let mut tables = Vec::new();
+                for value in array.iter() {
+                    match value.as_inline_table() {
+                        Some(table) => tables.push((table as &dyn TableLike, value.span())),
+                        None => {
+                            self.report(value.span(), format!("`{}` entries must be tables", key))
+                        }
+                    }
+                }
+                tables
+            }
+            _ => {
+                self.report(item.span(), format!("`{}` must be an array of tables", key));
+                Vec::new()
+            }
+        }
+    }
+
+    fn pipelines(&mut self, root: &toml_edit::Table) {
+        let item = match root.get("pipelines") {
+            Some(item) => item,
+            None => return,
+        };
+        // pipeline name -> line of first definition
+        let mut names: HashMap<String, usize> = HashMap::new();
+        for (table, span) in self.tables(item, "pipelines") {
+            self.fields(table, span.clone(), PIPELINE_FIELDS, "[[pipelines]]");
+            if let Some((k, item)) = table.get_key_value("name") {
+                if let Some(name) = item.as_str() {
+                    let line = self.position(k.span()).0;
+                    match names.get(name) {
+                        Some(first) => self.report(
+                            item.span(),
+                            format!(
+                                "duplicate pipeline name `{}` (first defined on line {})",
+                                name, first
+                            ),
+                        ),
+                        None => {
+                            names.insert(name.to_string(), line);
+                        }
+                    }
+                }
+            }
+            if let Some(item) = table.get("source") {
+                if let Some(source) = item.as_str() {
+                    if !self.source_names.contains(source) {
+                        self.report(item.span(), format!("no source named `{}`", source));
+                    }
+                }
+            }
+            if let Some(item) = table.get("destinations") {
+                if let Some(array) = item.as_array() {
+                    if array.is_empty() {
+                        self.report(
+                            item.span(),
+                            "`destinations` must name at least one destination".into(),
+                        );
+                    }
+                    for value in array.iter() {
+                        if let Some(destination) = value.as_str() {
+                            if !self.destination_names.contains(destination) {
+                                self.report(
+                                    value.span(),
+                                    format!("no destination named `{}`", destination),
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn sections(&mut self, root: &toml_edit::Table, key: &str, kind: Kind) {
+        let item = match root.get(key) {
+            Some(item) => item,
+            None => return,
+        };
+        for (table, span) in self.tables(item, key) {
+            self.section(table, span, key, kind);
+        }
+    }
+
+    fn section(
+        &mut self,
+        table: &dyn TableLike,
+        span: Option<Range<usize>>,
+        key: &str,
+        kind: Kind,
+    ) {
+        let context = format!("[[{}]]", key);
+        let connector = match table.get("type") {
+            None => {
+                self.report(
+                    span,
+                    format!("{} entry is missing the `type` field", context),
+                );
+                return;
+            }
+            Some(item) => match item.as_str() {
+                None => {
+                    self.report(item.span(), "`type` must be a string".into());
+                    return;
+                }
+                Some(section_type) => match connector::registry().find(kind, section_type) {
+                    Some(connector) => connector,
+                    None => {
+                        self.report(
+                            item.span(),
+                            format!("unknown {} type `{}`", kind, section_type),
+                        );
+                        return;
+                    }
+                },
+            },
+        };
+        let context = format!("{} `{}`", kind, connector.section_type);
+        let mut fields = vec![("display_name", FieldKind::String)];
+        fields.extend(connector.stored_fields().map(|field| {
+            let kind = match (field.field_type, field.is_optional()) {
+                (FieldType::Bool, false) => FieldKind::Bool,
+                (FieldType::Bool, true) => FieldKind::OptionalBool,
+                // optional integers are not used by any connector yet
+                (FieldType::Integer, _) => FieldKind::Integer,
+                (_, false) => FieldKind::String,
+                (_, true) => FieldKind::OptionalString,
+            };
+            (field.name.as_str(), kind)
+        }));
+        self.fields(table, span.clone(), &fields, &context);
+
+        if let Some((k, item)) = table.get_key_value("display_name") {
+            if let Some(name) = item.as_str() {
+                let line = self.position(k.span()).0;
+                match self.display_names.get(&(kind, name.to_string())) {
+                    Some(first) => {
+                        let message = format!(
+                            "duplicate display_name `{}` (first defined on line {})",
+                            name, first
+                        );
+                        self.report(item.span(), message);
+                    }
+                    None => {
+                        self.display_names.insert((kind, name.to_string()), line);
+                    }
+                }
+                match kind {
+                    Kind::Source => self.source_names.insert(name.to_string()),
+                    Kind::Destination => self.destination_names.insert(name.to_string()),
+                };
+            }
+        }
+        for field in connector.stored_fields() {
+            if let Some(item) = table.get(&field.name) {
+                self.value(field, item);
+            }
+        }
+        self.conflicts(connector, table, span);
+    }
+
+    // checks a single value against its field descriptor
+    fn value(&mut self, field: &connector::Field, item: &Item) {
+        let name = &field.name;
+        if let Some(value) = item.as_integer() {
+            if let Some(min) = field.min.filter(|min| value < *min) {
+                let message = format!("`{}` must be greater than {}", name, min - 1);
+                self.report(item.span(), message);
+            }
+            return;
+        }
+        let value = match item.as_str() {
+            Some(value) => value,
+            None => return,
+        };
+        match field.field_type {
+            FieldType::String if field.required && !field.secret && value.trim().is_empty() => {
+                self.report(item.span(), format!("`{}` must not be empty", name));
+            }
+            FieldType::Choice if !field.choices.iter().any(|choice| choice == value) => {
+                let message = format!(
+                    "`{}` must be one of {}, found `{}`",
+                    name,
+                    field.choices.join(", "),
+                    value
+                );
+                self.report(item.span(), message);
+            }
+            FieldType::Url if !secrets::is_reference(value) => {
+                let spec = field.url.as_ref().unwrap();
+                let schemes: Vec<&str> = spec.schemes.iter().map(String::as_str).collect();
+                let url = &secrets::mask_references(value);
+                if let Err(message) = check_connection_url(url, &schemes) {
+                    self.report(item.span(), message);
+                }
+            }
+            FieldType::Brokers if !secrets::has_reference(value) => {
+                if let Err(message) = connection::check_brokers(value) {
+                    self.report(item.span(), message);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // rules between the fields of a section
+    fn conflicts(
+        &mut self,
+        connector: &Connector,
+        table: &dyn TableLike,
+        span: Option<Range<usize>>,
+    ) {
+        let mut values = toml::Table::new();
+        for (key, item) in table.iter() {
+            let value = match item.as_value() {
+                Some(Value::String(value)) => toml::Value::String(value.value().clone()),
+                Some(Value::Boolean(value)) => toml::Value::Boolean(*value.value()),
+                Some(Value::Integer(value)) => toml::Value::Integer(*value.value()),
+                _ => continue,
+            };
+            values.insert(key.to_string(), value);
+        }
+        let item_span = |field: &connector::Field| table.get(&field.name).and_then(Item::span);
+        for conflict in connector.conflicts(&values) {
+            match conflict {
+                Conflict::Missing(fields, condition) => {
+                    for field in fields {
+                        let message = format!(
+                            "`{}` is required {}",
+                            field.name,
+                            connector.describe(condition, &values)
+                        );
+                        self.report(span.clone(), message);
+                    }
+                }
+                Conflict::NoneOf(fields) => {
+                    let names: Vec<String> = fields
+                        .iter()
+                        .map(|field| format!("`{}`", field.name))
+                        .collect();
+                    let message = format!(
+                        "{} {} needs either {}",
+                        connector.section_type,
+                        connector.kind,
+                        names.join(" or ")
+                    );
+                    self.report(span.clone(), message);
+                }
+                Conflict::Both(first, second) => self.report(
+                    item_span(second),
+                    format!("`{}` and `{}` can't both be set", first.name, second.name),
+                ),
+                Conflict::Requires(field, required) => self.report(
+                    item_span(field),
+                    format!("`{}` requires `{}`", field.name, required.name),
+                ),
+            }
+        }
+    }
+
+    fn fields(
+        &mut self,
+        table: &dyn TableLike,
+        span: Option<Range<usize>>,
+        fields: Fields,
+        context: &str,
+    ) {
+        for (name, kind) in fields {
+            match table.get(name) {
+                None if kind.is_optional() => {}
+                None => self.report(
+                    span.clone(),
+                    format!("{} is missing required field `{}`", context, name),
+                ),
+                Some(item) => match item.as_value() {
+                    Some(Value::String(string)) if kind.matches_reference() => {
+                        if let Err(message) = secrets::check_references(string.value()) {
+                            self.report(item.span(), message);
+                        }
+                    }
+                    Some(value) if kind.matches(value) => {}
+                    _ => self.report(item.span(), format!("`{}` must be {}", name, kind.name())),
+                },
+            }
+        }
+        for (key, _) in table.iter() {
+            if key != "type" && !fields.iter().any(|(name, _)| *name == key) {
+                let span = table.get_key_value(key).and_then(|(k, _)| Key::span(k));
+                self.report(span, format!("unknown field `{}` in {}", key, context));
+            }
+        }
+    }
+
+    fn report(&mut self, span: Option<Range<usize>>, message: String) {
+        let (line, column) = self.position(span);
+        self.diagnostics.push(Diagnostic {
+            line,
+            column,
+            message,
+        });
+    }
+
+    // converts a byte offset into a 1-based line and column
+    fn position(&self, span: Option<Range<usize>>) -> (usize, usize) {
+        let offset = span.map(|span| span.start).unwrap_or(0);
+        let offset = offset.min(self.contents.len());
+        let before = &self.contents[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let column = before[line_start..].chars().count() + 1;
+        (line, column)
+    }
+}
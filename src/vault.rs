@@ -0,0 +1,227 @@
+//! Local encrypted store for config secrets, referenced as `${vault:NAME}`.
+//!
+//! The vault is a TOML file holding a single ChaCha20-Poly1305 encrypted
+//! blob. The key is derived with Argon2id from a passphrase, or from the
+//! contents of a key file.
+
+use crate::prompt::{Prompter, Text};
+use crate::{output, Error, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+const VAULT_ENV: &str = "MYCELIAL_VAULT";
+const KEY_FILE_ENV: &str = "MYCELIAL_VAULT_KEY_FILE";
+const PASSPHRASE_ENV: &str = "MYCELIAL_VAULT_PASSPHRASE";
+
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+pub(crate) struct Vault {
+    path: PathBuf,
+    salt: [u8; 16],
+    key: Key,
+    entries: BTreeMap<String, String>,
+}
+
+/// Location of the vault, `~/.mycelial/vault` unless `MYCELIAL_VAULT` is set.
+pub(crate) fn vault_path() -> PathBuf {
+    match std::env::var_os(VAULT_ENV) {
+        Some(path) => PathBuf::from(path),
+        None => dirs::home_dir().unwrap().join(".mycelial").join("vault"),
+    }
+}
+
+// key file, then passphrase from the environment, then ask
+fn passphrase(p: &mut dyn Prompter, key_file: Option<&str>, creating: bool) -> Result<String> {
+    let key_file = key_file
+        .map(str::to_string)
+        .or_else(|| std::env::var(KEY_FILE_ENV).ok());
+    if let Some(key_file) = key_file {
+        return match fs::read_to_string(&key_file) {
+            Ok(contents) => Ok(contents.trim_end_matches(['\n', '\r']).to_string()),
+            Err(error) => Err(format!("could not read key file `{}`: {}", key_file, error).into()),
+        };
+    }
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+    crate::ensure(
+        "opening the vault",
+        &format!("set {} or pass --key-file", PASSPHRASE_ENV),
+    )?;
+    loop {
+        let instead = format!("--key-file or set {}", PASSPHRASE_ENV);
+        let passphrase = p.password(Text::new("Vault passphrase:").flag(&instead))?;
+        if !creating || p.password(Text::new("Repeat passphrase:").flag(&instead))? == passphrase {
+            return Ok(passphrase);
+        }
+        output::warn("passphrases don't match");
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key> {
+    if passphrase.is_empty() {
+        return Err("vault passphrase must not be empty".into());
+    }
+    let mut key = Key::default();
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|error| format!("could not derive vault key: {}", error))?;
+    Ok(key)
+}
+
+fn decode(field: &str, value: &str) -> Result<Vec<u8>> {
+    BASE64
+        .decode(value)
+        .map_err(|_| format!("vault is corrupted, `{}` is not valid base64", field).into())
+}
+
+impl Vault {
+    /// Opens the vault, creating an empty one if it doesn't exist yet.
+    pub(crate) fn open(p: &mut dyn Prompter, key_file: Option<&str>) -> Result<Vault> {
+        let path = vault_path();
+        if !path.exists() {
+            let mut salt = [0; 16];
+            OsRng.fill_bytes(&mut salt);
+            let key = derive_key(&passphrase(p, key_file, true)?, &salt)?;
+            return Ok(Vault {
+                path,
+                salt,
+                key,
+                entries: BTreeMap::new(),
+            });
+        }
+        let contents = fs::read_to_string(&path)?;
+        let file: VaultFile = toml::from_str(&contents)
+            .map_err(|error| format!("could not read vault `{}`: {}", path.display(), error))?;
+        if file.version != 1 {
+            return Err(format!("unsupported vault version {}", file.version).into());
+        }
+        let salt: [u8; 16] = decode("salt", &file.salt)?
+            .try_into()
+            .map_err(|_| "vault is corrupted, bad salt length")?;
+        let nonce = decode("nonce", &file.nonce)?;
+        if nonce.len() != 12 {
+            return Err("vault is corrupted, bad nonce length".into());
+        }
+        let ciphertext = decode("ciphertext", &file.ciphertext)?;
+        let key = derive_key(&passphrase(p, key_file, false)?, &salt)?;
+        let plaintext = ChaCha20Poly1305::new(&key)
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| "could not decrypt vault, wrong passphrase or key file")?;
+        let entries = toml::from_str(&String::from_utf8(plaintext)?)?;
+        Ok(Vault {
+            path,
+            salt,
+            key,
+            entries,
+        })
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&str> {
+        self.entries.get(name).map(String::as_str)
+    }
+
+    fn save(&self) -> Result<()> {
+        let plaintext = toml::to_string(&self.entries)?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = ChaCha20Poly1305::new(&self.key)
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| "could not encrypt vault")?;
+        let file = VaultFile {
+            version: 1,
+            salt: BASE64.encode(self.salt),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        };
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        crate::secrets::write_private(&self.path, &toml::to_string(&file)?)?;
+        Ok(())
+    }
+}
+
+pub fn set_secret(
+    p: &mut dyn Prompter,
+    name: &str,
+    value: Option<String>,
+    key_file: Option<&str>,
+) -> Result<()> {
+    let mut vault = Vault::open(p, key_file)?;
+    let value = match value {
+        Some(value) => value,
+        None => {
+            crate::ensure("`mycelial secrets set`", "pass --value")?;
+            p.password(Text::new(format!("Value for `{}`:", name)).flag("--value"))?
+        }
+    };
+    vault.entries.insert(name.to_string(), value);
+    vault.save()?;
+    output::say(
+        format!(
+            "secret `{}` saved, reference it as `${{vault:{}}}`",
+            name, name
+        )
+        .green(),
+    );
+    output::set("saved", name);
+    Ok(())
+}
+
+pub fn get_secret(p: &mut dyn Prompter, name: &str, key_file: Option<&str>) -> Result<()> {
+    let vault = Vault::open(p, key_file)?;
+    match vault.get(name) {
+        Some(value) => {
+            output::say(value);
+            output::set("name", name);
+            output::set("value", value);
+            Ok(())
+        }
+        None => Err(Error::Secret(format!("no secret named `{}` in the vault", name)).into()),
+    }
+}
+
+pub fn list_secrets(p: &mut dyn Prompter, key_file: Option<&str>) -> Result<()> {
+    if !vault_path().exists() {
+        output::say("the vault is empty".yellow());
+        output::set("secrets", Vec::<String>::new());
+        return Ok(());
+    }
+    let vault = Vault::open(p, key_file)?;
+    if vault.entries.is_empty() {
+        output::say("the vault is empty".yellow());
+    }
+    for name in vault.entries.keys() {
+        output::say(name);
+    }
+    output::set("secrets", vault.entries.keys().collect::<Vec<_>>());
+    Ok(())
+}
+
+pub fn remove_secret(p: &mut dyn Prompter, name: &str, key_file: Option<&str>) -> Result<()> {
+    if !vault_path().exists() {
+        return Err(Error::Secret(format!("no secret named `{}` in the vault", name)).into());
+    }
+    let mut vault = Vault::open(p, key_file)?;
+    if vault.entries.remove(name).is_none() {
+        return Err(Error::Secret(format!("no secret named `{}` in the vault", name)).into());
+    }
+    vault.save()?;
+    output::say(format!("secret `{}` removed", name).green());
+    output::set("removed", name);
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use assert_cmd::Command;
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+
+#[test]
+fn cli_exit_codes_tell_failures_apart() {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let mycelial = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("mycelial").unwrap();
+        cmd.current_dir(temp_dir.path())
+            .env("HOME", temp_dir.path())
+            .args(args);
+        cmd
+    };
+    let fails_with = |args: &[&str], code: i32, message: &str| {
+        mycelial(args)
+            .assert()
+            .code(code)
+            .stderr(predicate::str::contains(message));
+    };
+
+    fails_with(&["config", "frobnicate"], 2, "unrecognized subcommand");
+    fails_with(&["add"], 2, "--source and/or --destination");
+    fails_with(&["update"], 2, "--daemon and/or --control-plane");
+    fails_with(&["config", "validate"], 3, "could not read config file");
+    fails_with(
+        &["start", "--daemon"],
+        6,
+        "missing daemon binary, run `mycelial init --local`",
+    );
+    fails_with(
+        &["start", "--control-plane", "--token", "token"],
+        6,
+        "missing control plane binary",
+    );
+    fails_with(&["--non-interactive", "init"], 9, "pass --answers");
+    temp_dir.child("session.toml").write_str("").unwrap();
+    fails_with(
+        &["init", "--replay", "session.toml"],
+        9,
+        "has no answer for `Daemon Name:`",
+    );
+    temp_dir
+        .child("replay.toml")
+        .write_str(
+            "[[answers]]\nprompt = \"Daemon Name:\"\nanswer = \"d\"\n\
+             [[answers]]\nprompt = \"Daemon ID:\"\nanswer = \"d\"\n",
+        )
+        .unwrap();
+    fails_with(
+        &[
+            "init",
+            "--replay",
+            "replay.toml",
+            "--config",
+            "token.toml",
+            "--endpoint",
+            "http://localhost:7777",
+            "--token",
+            "${env:",
+        ],
+        2,
+        "--token: unterminated secret reference",
+    );
+
+    // mistakes in `add` flags are usage errors, a taken name a config error
+    temp_dir
+        .child("codes.toml")
+        .write_str(
+            "[node]\ndisplay_name = \"d\"\nunique_id = \"d\"\n\
+             storage_path = \"daemon.db\"\nauth_token = \"t\"\n\
+             [server]\nendpoint = \"http://localhost:7777\"\n",
+        )
+        .unwrap();
+    let add = |args: &[&'static str]| -> Vec<&'static str> {
+        let mut full = vec!["add", "--config", "codes.toml"];
+        full.extend_from_slice(args);
+        full
+    };
+    let sqlite = add(&["source", "sqlite", "--display-name", "S"]);
+    let sqlite = [&sqlite[..], &["--path", "data.db", "--query", "select 1"]].concat();
+    mycelial(&sqlite).assert().success();
+    fails_with(&sqlite, 3, "a source named `S` already exists");
+    let postgres = add(&["source", "postgres", "--display-name", "P"]);
+    let postgres = [
+        &postgres[..],
+        &["--url", "postgres://u:p@localhost/db", "--query", "q"],
+    ]
+    .concat();
+    fails_with(
+        &[&postgres[..], &["--ssl-ca", "missing.pem"]].concat(),
+        2,
+        "--ssl-ca: file `missing.pem` does not exist",
+    );
+    fails_with(
+        &[&postgres[..], &["--ssl-mode", "bogus"]].concat(),
+        2,
+        "--ssl-mode:",
+    );
+    fails_with(
+        &add(&[
+            "destination",
+            "kafka",
+            "--display-name",
+            "K",
+            "--brokers",
+            "localhost:9092",
+            "--topic",
+            "t",
+            "--security-protocol",
+            "sasl_ssl",
+        ]),
+        2,
+        "--sasl-mechanism is required",
+    );
+    mycelial(&add(&[
+        "destination",
+        "sqlite",
+        "--display-name",
+        "D",
+        "--path",
+        "out.db",
+    ]))
+    .assert()
+    .success();
+
+    // the sqlite source reads a database that doesn't exist
+    fails_with(
+        &["config", "test", "--config", "codes.toml"],
+        13,
+        "1 of 2 check(s) failed",
+    );
+    fails_with(
+        &["preview", "S", "--config", "codes.toml"],
+        13,
+        "error previewing `S`",
+    );
+    let check = add(&["--check", "source", "sqlite", "--display-name", "C"]);
+    fails_with(
+        &[&check[..], &["--path", "data.db", "--query", "select 1"]].concat(),
+        13,
+        "connection test of `C` failed",
+    );
+    fails_with(
+        &["preview", "D", "--config", "codes.toml"],
+        2,
+        "only sources can be previewed",
+    );
+    fails_with(&["secrets", "rm", "nope"], 14, "no secret named `nope`");
+
+    // a daemon that exits right away failed to start
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let daemon = temp_dir.child("myceliald");
+        daemon.write_str("#!/bin/sh\nexit 1\n").unwrap();
+        std::fs::set_permissions(daemon.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+        temp_dir
+            .child("config.toml")
+            .write_str("[node]\nstorage_path = \"daemon.db\"\n")
+            .unwrap();
+        fails_with(&["start", "--daemon"], 7, "daemon failed to start");
+    }
+    temp_dir.close().unwrap();
+}
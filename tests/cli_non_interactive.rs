@@ -0,0 +1,94 @@
+use assert_cmd::Command;
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+
+const CONFIG: &str = r#"
+[node]
+display_name = "My Daemon"
+unique_id = "my-daemon"
+storage_path = "daemon.db"
+auth_token = "token"
+
+[server]
+endpoint = "http://localhost:7777"
+
+[[sources]]
+type = "file"
+display_name = "File"
+path = "file.txt"
+"#;
+
+#[test]
+fn cli_non_interactive_names_the_missing_option() {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    temp_dir.child("config.toml").write_str(CONFIG).unwrap();
+    let mycelial = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("mycelial").unwrap();
+        cmd.current_dir(temp_dir.path())
+            .env("HOME", temp_dir.path())
+            .env_remove("MYCELIAL_VAULT_PASSPHRASE")
+            .env_remove("MYCELIAL_VAULT_KEY_FILE")
+            .args(args);
+        cmd
+    };
+    let fails_with = |args: &[&str], message: &str| {
+        mycelial(args)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(message));
+    };
+
+    fails_with(&["--non-interactive", "init"], "pass --answers");
+    fails_with(
+        &["add", "--source", "--non-interactive"],
+        "pass the source type",
+    );
+    fails_with(
+        &["config", "edit", "File", "--non-interactive"],
+        "mycelial config remove",
+    );
+    fails_with(
+        &["start", "--control-plane", "--non-interactive"],
+        "pass --token",
+    );
+    fails_with(
+        &["secrets", "set", "pg", "--non-interactive"],
+        "MYCELIAL_VAULT_PASSPHRASE",
+    );
+    mycelial(&["secrets", "set", "pg", "--non-interactive"])
+        .env("MYCELIAL_VAULT_PASSPHRASE", "passphrase")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("pass --value"));
+
+    // confirmations without a default need --yes
+    temp_dir.child("daemon.db").touch().unwrap();
+    fails_with(&["reset", "--daemon", "--non-interactive"], "pass --yes");
+    assert!(temp_dir.child("daemon.db").path().exists());
+    mycelial(&["reset", "--daemon", "--yes"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("daemon.db deleted"));
+    assert!(!temp_dir.child("daemon.db").path().exists());
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn cli_non_interactive_prompts_name_their_flag() {
+    use mycelial::prompt::{Terminal, Text};
+    use mycelial::{set_mode, Mode, Prompter};
+
+    set_mode(Mode::NonInteractive);
+    let error = Terminal
+        .password(Text::new("Security Token:").flag("--token"))
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "`Security Token:` can't be asked with --non-interactive, pass --token"
+    );
+    assert_eq!(mycelial::exit_code(error.as_ref()), 9);
+    let error = Terminal
+        .input(Text::new("Path:").default("file.txt").flag("--path"))
+        .unwrap_err();
+    assert!(error.to_string().ends_with("pass --path"));
+}
@@ -0,0 +1,98 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+
+#[test]
+fn cli_output_json_prints_one_document() {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let mycelial = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("mycelial").unwrap();
+        cmd.current_dir(temp_dir.path())
+            .env("HOME", temp_dir.path())
+            .args(args);
+        cmd
+    };
+    let document = |args: &[&str], code: i32| -> serde_json::Value {
+        let output = mycelial(args).assert().code(code).get_output().clone();
+        serde_json::from_slice(&output.stdout).unwrap()
+    };
+
+    let quickstart = document(&["--output", "json", "quickstart", "--no-start"], 0);
+    assert_eq!(quickstart["command"], "quickstart");
+    assert_eq!(quickstart["ok"], true);
+    assert_eq!(quickstart["config"], "config.toml");
+    assert_eq!(quickstart["sample_database"]["path"], "quickstart.db");
+    assert_eq!(quickstart["pipelines"][0], "quickstart");
+
+    // the flag is global, so it may follow the subcommand
+    let list = document(&["config", "list", "--output", "json"], 0);
+    assert_eq!(list["command"], "config list");
+    assert_eq!(list["entries"][0]["display_name"], "Sample Orders");
+    assert_eq!(list["entries"][1]["kind"], "destination");
+    let show = document(&["--output", "json", "config", "show", "Sample Orders"], 0);
+    assert_eq!(show["kind"], "source");
+    assert_eq!(show["entry"]["display_name"], "Sample Orders");
+
+    let destroy = document(&["--output", "json", "destroy"], 0);
+    assert_eq!(destroy["killed"], serde_json::json!([]));
+
+    // failures are reported in the document, with the exit code
+    let missing = [
+        "--output",
+        "json",
+        "config",
+        "validate",
+        "--config",
+        "missing.toml",
+    ];
+    let validate = document(&missing, 3);
+    assert_eq!(validate["ok"], false);
+    assert_eq!(validate["error"]["exit_code"], 3);
+    assert!(validate["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("could not read config file"));
+    mycelial(&missing)
+        .assert()
+        .stderr(predicate::str::is_empty());
+
+    // so are mistakes on the command line
+    let unknown = document(&["--output", "json", "start", "--bogus"], 2);
+    assert_eq!(unknown["command"], "start");
+    assert_eq!(unknown["ok"], false);
+    assert_eq!(unknown["error"]["exit_code"], 2);
+    assert!(unknown["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("unexpected argument '--bogus'"));
+    let save_anyway = [
+        "add",
+        "source",
+        "sqlite",
+        "--display-name",
+        "s",
+        "--path",
+        "s.db",
+        "--query",
+        "select 1",
+        "--save-anyway",
+        "--output=json",
+    ];
+    let add = document(&save_anyway, 2);
+    assert_eq!(add["command"], "add source sqlite");
+    assert_eq!(
+        add["error"]["message"],
+        "--save-anyway can only be used with --check"
+    );
+    mycelial(&["--output", "json", "--help"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Usage:"));
+
+    // text stays the default
+    mycelial(&["config", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Sample Orders"))
+        .stdout(predicate::str::contains("\"command\"").not());
+    temp_dir.close().unwrap();
+}
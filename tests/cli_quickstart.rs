@@ -0,0 +1,121 @@
+use assert_cmd::Command;
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+
+fn mycelial() -> Command {
+    Command::cargo_bin("mycelial").unwrap()
+}
+
+#[test]
+fn cli_quickstart_writes_sample_pipeline() {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let mycelial = |args: &[&str]| {
+        let mut cmd = mycelial();
+        cmd.current_dir(temp_dir.path()).args(args);
+        cmd
+    };
+
+    mycelial(&["quickstart", "--no-start"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "sample database `quickstart.db` has 100 orders",
+        ))
+        .stdout(predicate::str::contains(
+            "`quickstart`: Sample Orders -> Sample Replica",
+        ));
+    let contents = std::fs::read_to_string(temp_dir.child("config.toml").path()).unwrap();
+    let parsed: toml::Value = toml::from_str(&contents).unwrap();
+    assert_eq!(
+        parsed["sources"][0]["path"].as_str().unwrap(),
+        "quickstart.db"
+    );
+    assert_eq!(
+        parsed["destinations"][0]["path"].as_str().unwrap(),
+        "quickstart_replica.db"
+    );
+    assert_eq!(
+        parsed["pipelines"][0]["source"].as_str().unwrap(),
+        "Sample Orders"
+    );
+    assert_eq!(parsed["node"]["auth_token"].as_str().unwrap().len(), 32);
+    mycelial(&["config", "validate"]).assert().success();
+    mycelial(&[
+        "preview",
+        "Sample Orders",
+        "--limit",
+        "1",
+        "--output",
+        "json",
+    ])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(
+        "\"rows\":[{\"id\":1,\"customer\":",
+    ));
+
+    // never overwrites a config
+    mycelial(&["quickstart", "--no-start"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("`config.toml` already exists"));
+
+    mycelial(&[
+        "quickstart",
+        "--no-start",
+        "--hello-world",
+        "--config",
+        "hello.toml",
+    ])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(
+        "`quickstart`: Hello World Source -> Hello World Destination",
+    ));
+    mycelial(&["config", "validate", "--config", "hello.toml"])
+        .assert()
+        .success();
+    temp_dir.close().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_quickstart_starts_both_processes() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let executable = |name: &str, script: &str| {
+        let file = temp_dir.child(name);
+        file.write_str(script).unwrap();
+        std::fs::set_permissions(file.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+    };
+    executable(
+        "server",
+        "#!/bin/sh\necho \"$2\" > server.token\nexec sleep 30\n",
+    );
+    executable("myceliald", "#!/bin/sh\nexec sleep 30\n");
+    let mycelial = |args: &[&str]| {
+        let mut cmd = mycelial();
+        cmd.current_dir(temp_dir.path())
+            .env("HOME", temp_dir.path())
+            .args(args);
+        cmd
+    };
+
+    mycelial(&["quickstart", "--hello-world"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Control Plane started"))
+        .stdout(predicate::str::contains("daemon started!"))
+        .stdout(predicate::str::contains("Mycelial quickstart is running"));
+    let contents = std::fs::read_to_string(temp_dir.child("config.toml").path()).unwrap();
+    let parsed: toml::Value = toml::from_str(&contents).unwrap();
+    let token = std::fs::read_to_string(temp_dir.child("server.token").path()).unwrap();
+    assert_eq!(token.trim(), parsed["node"]["auth_token"].as_str().unwrap());
+    mycelial(&["destroy"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("killed daemon pid"))
+        .stdout(predicate::str::contains("killed control plane pid"));
+    temp_dir.close().unwrap();
+}
@@ -0,0 +1,238 @@
+use assert_cmd::Command;
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::os::unix::fs::PermissionsExt;
+use std::sync::{Arc, Mutex};
+
+// serves the same daemon archive for every request, recording the paths asked
+// for, returns the size of the archive too
+fn serve_release(content_length: bool) -> (String, Arc<Mutex<Vec<String>>>, usize) {
+    let mut archive = tar::Builder::new(flate2::write::GzEncoder::new(
+        Vec::new(),
+        flate2::Compression::default(),
+    ));
+    let daemon = b"#!/bin/sh\nexit 1\n";
+    let mut header = tar::Header::new_gnu();
+    header.set_size(daemon.len() as u64);
+    header.set_mode(0o755);
+    header.set_cksum();
+    archive
+        .append_data(&mut header, "myceliald", &daemon[..])
+        .unwrap();
+    let body = archive.into_inner().unwrap().finish().unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let paths = Arc::new(Mutex::new(Vec::new()));
+    let requested = paths.clone();
+    let size = body.len();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut request = String::new();
+            reader.read_line(&mut request).unwrap();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            let path = request.split(' ').nth(1).unwrap_or_default();
+            requested.lock().unwrap().push(path.to_string());
+            // without a length the body ends when the connection is closed
+            let length = match content_length {
+                true => format!("Content-Length: {}\r\n", body.len()),
+                false => String::new(),
+            };
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\n{}Connection: close\r\n\r\n",
+                length
+            )
+            .unwrap();
+            stream.write_all(&body).unwrap();
+        }
+    });
+    (url, paths, size)
+}
+
+#[test]
+fn cli_update_pins_a_release() {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let (url, paths, _) = serve_release(true);
+    let mycelial = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("mycelial").unwrap();
+        cmd.current_dir(temp_dir.path())
+            .env("HOME", temp_dir.path())
+            .env("MYCELIAL_RELEASES", &url)
+            .args(args);
+        cmd
+    };
+    let state = temp_dir.child(".mycelial/state.toml");
+
+    mycelial(&["update", "--daemon", "--version", "1.x"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "`1.x` is not a release version, expected vX.Y.Z or `latest`",
+        ));
+
+    mycelial(&["update", "--daemon", "--version", "0.4.2"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("pinned release v0.4.2"));
+    temp_dir
+        .child("myceliald")
+        .assert(predicate::path::exists());
+    state.assert(predicate::str::contains("pinned = \"v0.4.2\""));
+    state.assert(predicate::str::contains("daemon = \"v0.4.2\""));
+
+    // later downloads use the pinned release
+    mycelial(&["update", "--daemon"]).assert().success();
+    {
+        let paths = paths.lock().unwrap();
+        assert_eq!(paths.len(), 2);
+        for path in paths.iter() {
+            assert!(path.starts_with("/download/v0.4.2/myceliald-"), "{}", path);
+        }
+    }
+
+    // a new major version needs a confirmation
+    mycelial(&[
+        "--non-interactive",
+        "update",
+        "--daemon",
+        "--version",
+        "v0.5.0",
+    ])
+    .assert()
+    .code(12)
+    .stderr(predicate::str::contains(
+        "not updating the daemon from v0.4.2 to v0.5.0, a new major version",
+    ));
+    assert_eq!(paths.lock().unwrap().len(), 2);
+    mycelial(&["--yes", "update", "--daemon", "--version", "v0.5.0"])
+        .assert()
+        .success();
+    state.assert(predicate::str::contains("daemon = \"v0.5.0\""));
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn cli_update_asks_when_a_version_is_unknown() {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let (url, paths, _) = serve_release(true);
+    let mycelial = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("mycelial").unwrap();
+        cmd.current_dir(temp_dir.path())
+            .env("HOME", temp_dir.path())
+            .env("MYCELIAL_RELEASES", &url)
+            .args(args);
+        cmd
+    };
+    let daemon = temp_dir.child("myceliald");
+
+    // a daemon not downloaded by mycelial is asked for its version
+    daemon
+        .write_str("#!/bin/sh\necho \"myceliald 0.4.2\"\n")
+        .unwrap();
+    std::fs::set_permissions(daemon.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+    mycelial(&[
+        "--non-interactive",
+        "update",
+        "--daemon",
+        "--version",
+        "v0.5.0",
+    ])
+    .assert()
+    .code(12)
+    .stderr(predicate::str::contains(
+        "not updating the daemon from v0.4.2 to v0.5.0, a new major version",
+    ));
+    mycelial(&[
+        "--non-interactive",
+        "update",
+        "--daemon",
+        "--version",
+        "v0.4.3",
+    ])
+    .assert()
+    .success();
+
+    // the downloaded one can't tell
+    mycelial(&[
+        "--non-interactive",
+        "update",
+        "--daemon",
+        "--version",
+        "v0.4.4",
+    ])
+    .assert()
+    .success();
+    temp_dir
+        .child(".mycelial/state.toml")
+        .assert(predicate::str::contains("daemon = \"v0.4.4\""));
+    std::fs::remove_file(temp_dir.child(".mycelial/state.toml").path()).unwrap();
+    mycelial(&[
+        "--non-interactive",
+        "update",
+        "--daemon",
+        "--version",
+        "v0.4.5",
+    ])
+    .assert()
+    .code(12)
+    .stderr(predicate::str::contains(
+        "not updating the daemon from an unknown version to v0.4.5 without confirmation",
+    ));
+
+    // the latest release redirects nowhere, so its version is unknown
+    mycelial(&["-y", "update", "--daemon", "--version", "v0.4.5"])
+        .assert()
+        .success();
+    mycelial(&[
+        "--non-interactive",
+        "update",
+        "--daemon",
+        "--version",
+        "latest",
+    ])
+    .assert()
+    .code(12)
+    .stderr(predicate::str::contains(
+        "not updating the daemon from v0.4.5 to the latest release, whose version is unknown",
+    ));
+    let downloads = paths.lock().unwrap().len();
+    mycelial(&["-y", "update", "--daemon", "--version", "latest"])
+        .assert()
+        .success();
+    assert!(paths.lock().unwrap().len() > downloads);
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn cli_update_counts_bytes_without_content_length() {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let (url, _, size) = serve_release(false);
+
+    let output = Command::cargo_bin("mycelial")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .env("HOME", temp_dir.path())
+        .env("MYCELIAL_RELEASES", &url)
+        .args([
+            "--output",
+            "json",
+            "update",
+            "--daemon",
+            "--version",
+            "0.4.2",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let document: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(document["downloaded"][0]["bytes"], size);
+    temp_dir.close().unwrap();
+}
@@ -0,0 +1,153 @@
+use assert_cmd::Command;
+use assert_fs::prelude::*;
+use mycelial::prompt::{Answer, Recorder, Script};
+use predicates::prelude::*;
+use rexpect::session::spawn_command;
+
+fn file_source_answers(auth_token: &str) -> Vec<Answer> {
+    vec![
+        Answer::new("Daemon Name:", "My Daemon"),
+        Answer::new("Daemon ID:", "my-daemon"),
+        Answer::new("Control Plane:", ""),
+        Answer::new("Auth Token:", auth_token),
+        Answer::new("What would you like to do?", "Add Source"),
+        Answer::new("What type of source would you like to add?", "File source"),
+        Answer::new("Display name:", "my file source"),
+        Answer::new("Path:", "in.txt"),
+        Answer::new("What would you like to do?", "Exit"),
+    ]
+}
+
+#[tokio::test]
+async fn create_config_from_script() {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let config_file = temp_dir.child("config.toml");
+    let session_file = temp_dir.child("session.toml");
+    let script = Script::new(file_source_answers("token"));
+    let mut recorder = Recorder::new(Box::new(script), session_file.path());
+    mycelial::create_config(
+        &mut recorder,
+        config_file.path().display().to_string(),
+        None,
+        None,
+        None,
+        None,
+    )
+    .await
+    .unwrap();
+
+    let contents = std::fs::read_to_string(config_file.path()).unwrap();
+    let parsed: toml::Value = toml::from_str(&contents).unwrap();
+    // an empty answer takes the default
+    assert_eq!(
+        parsed["server"]["endpoint"].as_str().unwrap(),
+        "http://localhost:7777"
+    );
+    assert_eq!(parsed["node"]["auth_token"].as_str().unwrap(), "token");
+    assert_eq!(parsed["sources"][0]["path"].as_str().unwrap(), "in.txt");
+
+    // the plain token isn't recorded, so the session can't be replayed
+    let recorded = std::fs::read_to_string(session_file.path()).unwrap();
+    assert!(!recorded.contains("\"token\""));
+    let mut replay = Script::load(&session_file.path().display().to_string()).unwrap();
+    let error = mycelial::create_config(
+        &mut replay,
+        temp_dir.child("replayed.toml").path().display().to_string(),
+        None,
+        None,
+        None,
+        None,
+    )
+    .await
+    .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("secrets are only recorded as references"));
+
+    // answers are checked like typed ones
+    let mut script = Script::new(file_source_answers("${env:}"));
+    let error = mycelial::create_config(
+        &mut script,
+        temp_dir.child("invalid.toml").path().display().to_string(),
+        None,
+        None,
+        None,
+        None,
+    )
+    .await
+    .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("is not an answer to `Auth Token:`"));
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn cli_record_and_replay_init() {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let bin_path = assert_cmd::cargo::cargo_bin("mycelial");
+    let mut cmd = std::process::Command::new(bin_path);
+    cmd.current_dir(temp_dir.path())
+        .args(["--record", "session.toml", "init"]);
+    let mut session = spawn_command(cmd, Some(1_000)).unwrap();
+    session.exp_string("Daemon Name:").unwrap();
+    session.send_line("My Daemon").unwrap();
+    session.exp_string("Daemon ID:").unwrap();
+    session.send_line("my-daemon").unwrap();
+    session.exp_string("Control Plane:").unwrap();
+    session.send_line("http://localhost:8080").unwrap();
+    session.exp_string("Auth Token:").unwrap();
+    session.send_line("${env:MYCELIAL_TOKEN}").unwrap();
+    session.exp_string("What would you like to do?").unwrap();
+    session.send("Add Source").unwrap();
+    session.exp_string("Add Source").unwrap();
+    session.send_line("").unwrap();
+    session.send("File source").unwrap();
+    session.exp_string("File source").unwrap();
+    session.send_line("").unwrap();
+    session.exp_string("Display name:").unwrap();
+    session.send_line("my file source").unwrap();
+    session.exp_string("Path:").unwrap();
+    session.send_line("in.txt").unwrap();
+    session.exp_string("What would you like to do?").unwrap();
+    session.send("Exit").unwrap();
+    session.exp_string("Exit").unwrap();
+    session.send_line("").unwrap();
+    session.exp_eof().unwrap();
+
+    let mycelial = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("mycelial").unwrap();
+        cmd.current_dir(temp_dir.path()).args(args);
+        cmd
+    };
+    mycelial(&[
+        "init",
+        "--replay",
+        "session.toml",
+        "--config",
+        "replayed.toml",
+    ])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("replayed.toml saved!"));
+    let load = |name: &str| -> toml::Value {
+        let contents = std::fs::read_to_string(temp_dir.child(name).path()).unwrap();
+        toml::from_str(&contents).unwrap()
+    };
+    let (recorded, replayed) = (load("config.toml"), load("replayed.toml"));
+    assert_eq!(replayed["sources"], recorded["sources"]);
+    assert_eq!(replayed["server"], recorded["server"]);
+    assert_eq!(
+        replayed["node"]["auth_token"].as_str().unwrap(),
+        "${env:MYCELIAL_TOKEN}"
+    );
+
+    // a session only fits the prompts it was recorded from
+    mycelial(&["add", "--source", "--replay", "session.toml"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "answers `Daemon Name:` where `What type of source would you like to add?` is asked",
+        ));
+    temp_dir.close().unwrap();
+}